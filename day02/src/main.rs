@@ -1,17 +1,22 @@
 use core::fmt;
+use std::io;
 
-fn main() {
+use intcode::patch::PatchFile;
+
+fn main() -> io::Result<()> {
     let input = std::fs::read_to_string("input.txt").unwrap();
     let code: Vec<usize> = input
         .trim()
         .split(',')
         .map(|s| s.parse().unwrap())
         .collect();
-    let mut code1 = code.clone();
-    code1[1] = 12;
-    code1[2] = 2;
+    // The patches are written for intcode programs, whose cells are signed
+    let mut code1: Vec<isize> = code.iter().map(|&n| n as isize).collect();
+    PatchFile::apply_from("patches.txt", "1202-alarm", &mut code1)?;
+    let code1 = code1.into_iter().map(|n| n as usize).collect();
     println!("1: {}", VM::init(code1).run());
     println!("2: {}", answer2(code, 19690720));
+    Ok(())
 }
 
 fn answer2(code: Vec<usize>, target: usize) -> usize {
@@ -144,7 +144,7 @@ impl Circuit {
 
     fn poll_run(&mut self) -> Poll<()> {
         while let Some((vm, st)) = self.vms.iter_mut().find(|(_, st)| *st == State::Ready) {
-            if vm.run().is_ready() {
+            if vm.run().is_halted() {
                 *st = State::Halt;
             } else {
                 *st = State::Pending
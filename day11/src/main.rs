@@ -50,7 +50,7 @@ impl Robot {
     }
 
     fn run(&mut self) {
-        while !self.vm.run().is_ready() {
+        while !self.vm.run().is_halted() {
             self.process_output();
             let white = if self.white.contains(&self.pos) { 1 } else { 0 };
             self.vm.write_port(&[white]);
@@ -15,7 +15,7 @@ use crossterm::{
     terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use day13::{Console, Game, Tile};
-use intcode::parse_program;
+use intcode::{parse_program, patch::PatchFile};
 
 fn main() -> io::Result<()> {
     env_logger::init();
@@ -33,13 +33,13 @@ fn main() -> io::Result<()> {
 fn mainloop() -> io::Result<()> {
     let input = std::fs::read_to_string("input.txt").unwrap();
     let mut program = parse_program(&input);
-    program[0] = 2;
+    PatchFile::apply_from("patches.txt", "free-play", &mut program)?;
     let console = Rc::new(RefCell::new(TUI::init((21, 38))));
     console.borrow_mut().clearscreen()?;
     console.borrow_mut().flush()?;
     let mut game = Game::init(console.clone(), program);
 
-    while game.run().is_pending() {
+    while !game.run().is_halted() {
         console.borrow_mut().flush()?;
         let autoplay = console.borrow().autoplay;
         let joystick = read_joystick(autoplay)?;
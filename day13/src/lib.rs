@@ -1,6 +1,6 @@
-use intcode::VM;
+use intcode::{Status, VM};
 
-use std::{cell::RefCell, rc::Rc, task::Poll};
+use std::{cell::RefCell, rc::Rc};
 
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
@@ -33,8 +33,8 @@ impl<C: Console> Game<C> {
         }
     }
 
-    pub fn run(&mut self) -> Poll<()> {
-        let poll = self.vm.run();
+    pub fn run(&mut self) -> Status {
+        let status = self.vm.run();
         let mut buf: [isize; 3] = [0; 3];
         while self.vm.read_exact(&mut buf[..]).is_ready() {
             let [x, y, c] = buf;
@@ -46,7 +46,7 @@ impl<C: Console> Game<C> {
                 self.console.borrow_mut().draw(pos, t);
             }
         }
-        poll
+        status
     }
 
     pub fn joystick_input(&mut self, input: isize) {
@@ -1,19 +1,19 @@
 #![feature(array_chunks)]
 #![feature(unsigned_signed_diff)]
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, io, rc::Rc};
 
 use day13::{Console, Game, Tile};
-use intcode::parse_program;
+use intcode::{parse_program, patch::PatchFile};
 
-fn main() {
+fn main() -> io::Result<()> {
     env_logger::init();
 
     let input = std::fs::read_to_string("input.txt").unwrap();
     let mut program = parse_program(&input);
     let counter = Rc::new(RefCell::new(CountConsole::new()));
     let mut game = Game::init(counter.clone(), program.clone());
-    assert!(game.run().is_ready());
+    assert!(game.run().is_halted());
     let blocks = counter
         .borrow()
         .tiles
@@ -23,13 +23,14 @@ fn main() {
     println!("1: {}", blocks);
 
     let console = Rc::new(RefCell::new(AutoConsole::default()));
-    program[0] = 2;
+    PatchFile::apply_from("patches.txt", "free-play", &mut program)?;
     let mut game = Game::init(console.clone(), program.clone());
-    while !game.run().is_ready() {
+    while !game.run().is_halted() {
         let input = console.borrow().auto_joystick();
         game.joystick_input(input);
     }
     println!("2: {}", console.borrow().score);
+    Ok(())
 }
 
 struct CountConsole {
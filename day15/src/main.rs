@@ -48,7 +48,7 @@ fn find_oxygen(program: Vec<isize>) -> Option<(VM, usize)> {
             if dist0 + 1 < dist1 {
                 let mut vm = dist_map[&p0].vm.clone();
                 vm.write_port(&[(dir + 1) as isize]);
-                if vm.run().is_ready() {
+                if vm.run().is_halted() {
                     panic!("VM halted");
                 }
                 match vm.read_port() {
@@ -112,7 +112,7 @@ fn fill_oxygen(vm: VM) -> usize {
             if dist0 + 1 < dist1 {
                 let mut vm = dist_map[&p0].vm.clone();
                 vm.write_port(&[(dir + 1) as isize]);
-                if vm.run().is_ready() {
+                if vm.run().is_halted() {
                     panic!("VM halted");
                 }
                 match vm.read_port() {
@@ -4,15 +4,15 @@
 
 use std::{
     collections::{HashMap, HashSet},
-    fmt,
+    fmt, io,
 };
 
 use bitvec::{bitbox, boxed::BitBox};
-use intcode::{VM, parse_program};
+use intcode::{VM, parse_program, patch::PatchFile};
 use log::{info, log_enabled};
 use ndarray::Array2;
 
-fn main() {
+fn main() -> io::Result<()> {
     env_logger::init();
 
     let input = std::fs::read_to_string("input.txt").unwrap();
@@ -27,7 +27,7 @@ fn main() {
     info!("walk: {}", serialize(&walk));
     let code = Code::compress(&walk);
 
-    program[0] = 2;
+    PatchFile::apply_from("patches.txt", "wake-up", &mut program)?;
     let mut vm = VM::init(program);
     for routine in code.serialize() {
         info!("program: {}", routine);
@@ -57,6 +57,7 @@ fn main() {
         }
     }
     println!("2: {}", score);
+    Ok(())
 }
 
 #[test]
@@ -73,7 +74,7 @@ fn test2() {
 
 fn get_camera(program: Vec<isize>) -> String {
     let mut vm = VM::init(program);
-    assert!(vm.run().is_ready());
+    assert!(vm.run().is_halted());
     String::from_utf8(
         vm.read_all()
             .into_iter()
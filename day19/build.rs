@@ -0,0 +1,10 @@
+use std::{env, fs, path::Path};
+
+use intcode::{parse_program, transpile::transpile};
+
+fn main() {
+    println!("cargo::rerun-if-changed=input.txt");
+    let input = fs::read_to_string("input.txt").unwrap();
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("program.rs");
+    fs::write(out, transpile(&parse_program(&input))).unwrap();
+}
@@ -1,15 +1,17 @@
 #![feature(try_blocks)]
 
-use intcode::{VM, parse_program};
 use log::{info, log_enabled};
 use ndarray::Array2;
 
+// input.txt transpiled to Rust by build.rs
+mod program {
+    include!(concat!(env!("OUT_DIR"), "/program.rs"));
+}
+
 fn main() {
     env_logger::init();
 
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let program = parse_program(&input);
-    let q = VMQuery { program };
+    let q = VMQuery;
     let (image, count) = scan(&q, 50);
     if log_enabled!(log::Level::Info) {
         for y in 0..50 {
@@ -29,15 +31,13 @@ trait Query {
     fn query(&self, x: usize, y: usize) -> bool;
 }
 
-struct VMQuery {
-    program: Vec<isize>,
-}
+struct VMQuery;
 
 impl Query for VMQuery {
     fn query(&self, x: usize, y: usize) -> bool {
-        let mut vm = VM::init(self.program.clone());
+        let mut vm = program::init();
         vm.write_port(&[x as isize, y as isize]);
-        if !vm.run().is_ready() {
+        if !vm.run().is_halted() {
             panic!("VM should have shut down");
         }
         match vm.read_port().unwrap() {
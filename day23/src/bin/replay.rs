@@ -0,0 +1,99 @@
+use std::{collections::BTreeMap, fs, io};
+
+use day23::{Capture, NAT};
+use intcode::cli::{Args, invalid_data};
+
+static USAGE: &str = "\
+Usage: replay [OPTIONS] <CAPTURE>
+
+Replay a packet capture written by `main --capture`, and summarise the
+traffic of each node and the NAT.
+
+Options:
+  -n, --node <PID>  Only replay packets from or to PID
+  -q, --quiet       Only print the summary
+  -h, --help        Print this help";
+
+fn node_name(pid: usize) -> String {
+    if pid == NAT {
+        "NAT".to_string()
+    } else {
+        pid.to_string()
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = Args::new(USAGE);
+    let mut path = None;
+    let mut node = None;
+    let mut quiet = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            "-n" | "--node" => {
+                node = Some(args.parse::<usize>(&arg)?);
+            }
+            "-q" | "--quiet" => {
+                quiet = true;
+            }
+            _ if arg.starts_with('-') => {
+                return Err(Args::unknown(&arg));
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let path = path.ok_or_else(|| args.usage())?;
+    let capture: Capture = fs::read_to_string(path)?.parse().map_err(invalid_data)?;
+
+    // (sent, received) per node
+    let mut traffic: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    // (tick, y, packets routed since the previous injection)
+    let mut injections = Vec::new();
+    let mut since_injection = 0;
+
+    for r in &capture.records {
+        traffic.entry(r.src).or_default().0 += 1;
+        traffic.entry(r.dst).or_default().1 += 1;
+        if r.nat {
+            injections.push((r.tick, r.data[1], since_injection));
+            since_injection = 0;
+        } else {
+            since_injection += 1;
+        }
+
+        if !quiet && node.is_none_or(|pid| r.src == pid || r.dst == pid) {
+            println!(
+                "tick {:6}  {:>3} -> {:>3}  ({}, {}){}",
+                r.tick,
+                node_name(r.src),
+                node_name(r.dst),
+                r.data[0],
+                r.data[1],
+                if r.nat { "  [idle]" } else { "" }
+            );
+        }
+    }
+
+    if !quiet {
+        println!();
+    }
+    println!("{} packets", capture.records.len());
+    println!("node   sent   recv");
+    for (&pid, &(sent, recv)) in &traffic {
+        println!("{:>4} {:6} {:6}", node_name(pid), sent, recv);
+    }
+
+    println!();
+    println!("NAT injections: {}", injections.len());
+    println!("  tick       y  routed");
+    for &(tick, y, routed) in &injections {
+        println!("{:6} {:7} {:7}", tick, y, routed);
+    }
+    // The network converges once this repeats the last injection
+    if let Some(r) = capture.records.iter().rev().find(|r| r.dst == NAT) {
+        println!("Last packet to the NAT: ({}, {})", r.data[0], r.data[1]);
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::{fmt, str::FromStr};
+
+/// Address of the NAT
+pub const NAT: usize = 255;
+
+/// A packet as it was routed by the network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Number of process runs before the packet was routed
+    pub tick: usize,
+    pub src: usize,
+    pub dst: usize,
+    pub data: [isize; 2],
+    /// Injected by the NAT when the network went idle
+    pub nat: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capture {
+    pub records: Vec<Record>,
+}
+
+impl Capture {
+    pub fn record(&mut self, record: Record) {
+        self.records.push(record);
+    }
+}
+
+/// One line per packet: `tick src dst x y nat`. Lines starting with `#` are comments.
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# tick src dst x y nat")?;
+        for r in &self.records {
+            writeln!(
+                f,
+                "{} {} {} {} {} {}",
+                r.tick, r.src, r.dst, r.data[0], r.data[1], r.nat as u8
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Capture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut capture = Capture::default();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line
+                .split_whitespace()
+                .map(|s| s.parse::<isize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Invalid line {:?}: {}", line, e))?;
+            let &[tick, src, dst, x, y, nat @ (0 | 1)] = &fields[..] else {
+                return Err(format!("Invalid line {:?}", line));
+            };
+            let unsigned =
+                |n: isize| usize::try_from(n).map_err(|_| format!("Invalid line {:?}", line));
+            capture.record(Record {
+                tick: unsigned(tick)?,
+                src: unsigned(src)?,
+                dst: unsigned(dst)?,
+                data: [x, y],
+                nat: nat == 1,
+            });
+        }
+        Ok(capture)
+    }
+}
+
+#[test]
+fn test_capture() {
+    let capture = Capture {
+        records: vec![
+            Record {
+                tick: 3,
+                src: 7,
+                dst: NAT,
+                data: [12, -5],
+                nat: false,
+            },
+            Record {
+                tick: 60,
+                src: NAT,
+                dst: 0,
+                data: [12, -5],
+                nat: true,
+            },
+        ],
+    };
+    let text = capture.to_string();
+    assert_eq!(
+        text,
+        "# tick src dst x y nat\n3 7 255 12 -5 0\n60 255 0 12 -5 1\n"
+    );
+    assert_eq!(text.parse::<Capture>().unwrap(), capture);
+    assert!("1 2 3 4 5 2".parse::<Capture>().is_err());
+}
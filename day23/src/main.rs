@@ -1,20 +1,74 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, fs, io};
 
 use bitvec::{bitbox, boxed::BitBox};
-use intcode::{VM, parse_program};
+use day23::{Capture, NAT, Record};
+use intcode::{
+    VM,
+    cli::{Args, invalid_input},
+    parse_program,
+};
 
-fn main() {
+mod threaded;
+
+static USAGE: &str = "\
+Usage: main [OPTIONS]
+
+Boot the network of input.txt, and print the first packet to the NAT and the
+first value it sends twice in a row.
+
+Options:
+  -t, --threaded        Run every NIC on its own thread instead of the scheduler
+  -c, --capture <FILE>  Write the packets routed in part 2 to FILE, for `replay`.
+                        Not supported with --threaded
+  -h, --help            Print this help";
+
+fn main() -> io::Result<()> {
     env_logger::init();
 
-    let input = std::fs::read_to_string("input.txt").unwrap();
+    let mut args = Args::new(USAGE);
+    let mut threaded = false;
+    let mut capture = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            "-t" | "--threaded" => {
+                threaded = true;
+            }
+            "-c" | "--capture" => {
+                capture = Some(args.value(&arg)?);
+            }
+            _ => return Err(Args::unknown(&arg)),
+        }
+    }
+    if threaded && capture.is_some() {
+        return Err(invalid_input("--capture is not supported with --threaded"));
+    }
+
+    let input = fs::read_to_string("input.txt")?;
     let program = parse_program(&input);
 
+    if threaded {
+        let nat = threaded::Network::boot(program.clone(), 50)
+            .run_until_nat()
+            .unwrap();
+        println!("1: {}", nat[1]);
+        println!("2: {}", threaded::Network::boot(program, 50).run());
+        return Ok(());
+    }
+
     let mut os = OS::boot(program.clone(), 50);
     let nat = os.run_until_nat().unwrap();
     println!("1: {}", nat[1]);
 
     let mut os = OS::boot(program, 50);
+    if capture.is_some() {
+        os.enable_capture();
+    }
     println!("2: {}", os.run());
+    if let (Some(path), Some(capture)) = (capture, os.take_capture()) {
+        fs::write(path, capture.to_string())?;
+    }
+    Ok(())
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -54,6 +108,7 @@ impl Scheduler {
 }
 
 struct Packet {
+    src: usize,
     pid: usize,
     data: [isize; 2],
 }
@@ -62,6 +117,8 @@ struct OS {
     process: Vec<Process>,
     scheduler: Scheduler,
     queue: VecDeque<Packet>,
+    tick: usize,
+    capture: Option<Capture>,
 }
 
 impl OS {
@@ -82,6 +139,28 @@ impl OS {
             process,
             scheduler: Scheduler::init(n),
             queue: VecDeque::new(),
+            tick: 0,
+            capture: None,
+        }
+    }
+
+    fn enable_capture(&mut self) {
+        self.capture.get_or_insert_with(Capture::default);
+    }
+
+    fn take_capture(&mut self) -> Option<Capture> {
+        self.capture.take()
+    }
+
+    fn record(&mut self, packet: &Packet, nat: bool) {
+        if let Some(capture) = &mut self.capture {
+            capture.record(Record {
+                tick: self.tick,
+                src: packet.src,
+                dst: packet.pid,
+                data: packet.data,
+                nat,
+            });
         }
     }
 
@@ -89,7 +168,8 @@ impl OS {
         while let Some(pid) = self.scheduler.next() {
             self.run_proc(pid);
             while let Some(packet) = self.queue.pop_front() {
-                if packet.pid == 255 {
+                self.record(&packet, false);
+                if packet.pid == NAT {
                     return Some(packet.data);
                 }
                 self.write_packet(packet);
@@ -107,7 +187,8 @@ impl OS {
             while let Some(pid) = self.scheduler.next() {
                 self.run_proc(pid);
                 while let Some(packet) = self.queue.pop_front() {
-                    if packet.pid == 255 {
+                    self.record(&packet, false);
+                    if packet.pid == NAT {
                         log::info!("RECV: {:?}", packet.data);
                         nat = Some(packet.data);
                     } else {
@@ -131,7 +212,13 @@ impl OS {
                     _ => (),
                 }
 
-                self.write_packet(Packet { pid: 0, data });
+                let packet = Packet {
+                    src: NAT,
+                    pid: 0,
+                    data,
+                };
+                self.record(&packet, true);
+                self.write_packet(packet);
             } else {
                 panic!("NAT packet empty");
             }
@@ -156,6 +243,7 @@ impl OS {
         if proc.st == State::Halt {
             return;
         }
+        self.tick += 1;
 
         if proc.queue.is_empty() {
             proc.vm.write_port(&[-1]);
@@ -165,16 +253,19 @@ impl OS {
             }
         }
 
-        if proc.vm.run().is_ready() {
+        if proc.vm.run().is_halted() {
             proc.st = State::Halt;
         } else {
             proc.st = State::Sleep;
         }
         let mut buf = [0; 3];
         while proc.vm.read_exact(&mut buf[..]).is_ready() {
-            let [pid, x, y] = buf;
-            let pid = pid.try_into().unwrap();
-            self.queue.push_back(Packet { pid, data: [x, y] });
+            let [dst, x, y] = buf;
+            self.queue.push_back(Packet {
+                src: pid,
+                pid: dst.try_into().unwrap(),
+                data: [x, y],
+            });
         }
     }
 }
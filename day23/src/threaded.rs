@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use day23::NAT;
+use intcode::{
+    VM,
+    threaded::{InputMode, Runtime},
+};
+
+// How long a NIC waits for a packet before it reads -1
+const POLL: Duration = Duration::from_millis(1);
+// How long the network has to stay quiet before the NAT considers it idle
+const SETTLE: Duration = Duration::from_millis(20);
+
+/// Same network as `OS`, with every NIC on its own thread.
+pub struct Network {
+    rt: Runtime,
+    partial: Vec<Vec<isize>>,
+}
+
+impl Network {
+    pub fn boot(program: Vec<isize>, n: usize) -> Self {
+        let mut rt = Runtime::new();
+        for pid in 0..n {
+            let mut vm = VM::init(program.clone());
+            // Init network addr
+            vm.write_port(&[pid as isize]);
+            rt.spawn(vm, InputMode::Timeout {
+                timeout: POLL,
+                value: -1,
+            });
+        }
+        Self {
+            rt,
+            partial: vec![Vec::new(); n],
+        }
+    }
+
+    // Route packets until the network is idle, or until `on_nat` returns true
+    fn route(&mut self, mut on_nat: impl FnMut([isize; 2]) -> bool) {
+        loop {
+            while let Some((src, v)) = self.rt.recv_timeout(POLL) {
+                let buf = &mut self.partial[src];
+                buf.push(v);
+                if let &[dst, x, y] = &buf[..] {
+                    buf.clear();
+                    if dst == NAT as isize {
+                        if on_nat([x, y]) {
+                            return;
+                        }
+                    } else {
+                        self.rt.send(dst.try_into().unwrap(), &[x, y]);
+                    }
+                }
+            }
+            if self.partial.iter().all(Vec::is_empty) && self.rt.wait_idle(SETTLE) {
+                return;
+            }
+        }
+    }
+
+    pub fn run_until_nat(mut self) -> Option<[isize; 2]> {
+        let mut nat = None;
+        self.route(|data| {
+            nat = Some(data);
+            true
+        });
+        nat
+    }
+
+    pub fn run(mut self) -> isize {
+        let mut nat = None;
+        let mut last_y = None;
+
+        loop {
+            self.route(|data| {
+                log::info!("RECV: {:?}", data);
+                nat = Some(data);
+                false
+            });
+
+            let Some(data) = nat else {
+                panic!("NAT packet empty");
+            };
+            log::info!("SEND: {:?}", data);
+            if last_y == Some(data[1]) {
+                return data[1];
+            }
+            last_y = Some(data[1]);
+            self.rt.send(0, &data);
+        }
+    }
+}
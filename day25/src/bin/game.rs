@@ -1,27 +1,443 @@
-use std::io;
+use std::{fs, io, path::PathBuf, process};
 
-use day25::{convert_ascii, write_console};
-use intcode::{VM, parse_program};
+use bitvec::{bitbox, boxed::BitBox};
+use day25::{
+    CACHE_DIR, Event, Graph, Path, Response, STEP_LIMIT, Verdict, append, check_weight,
+    convert_ascii, read_ascii, read_transcript, replay, solve_weight, write_transcript,
+};
+use intcode::{Status, VM, cli::Args, parse_program, testing::Transcript};
+use rustyline::error::ReadlineError;
 
-fn rl_error(e: rustyline::error::ReadlineError) -> io::Error {
+static USAGE: &str = "\
+Usage: game [OPTIONS]
+
+Play the game in input.txt.
+
+Options:
+  -r, --record <FILE>  Write the commands and the responses to a transcript FILE
+                       when leaving the game
+  -p, --replay <FILE>  Feed the commands of a transcript FILE to the game, and
+                       check that the responses match, instead of playing
+  -h, --help           Print this help
+
+A game can't be recorded after :load, or when it gets stuck in a loop.";
+
+static HELP: &str = "\
+Meta-commands:
+  :save <slot>            Save the game to saves/<slot>.save
+  :load <slot>            Load a saved game
+  :undo                   Take back the last command
+  :history                List the commands that can be taken back
+  :goto <room>            Walk to a room along the shortest path
+  :collect [<item>, ...]  Pick up the items along the shortest route, or all
+                          the safe items
+  :solve                  Go to the checkpoint, and find which of the items
+                          held get through the pressure-sensitive floor
+  :help                   Print this help";
+
+/// Directory of the save slots, relative to the working directory
+static SAVE_DIR: &str = "saves";
+
+fn rl_error(e: ReadlineError) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidInput, e)
 }
 
+/// The game right before a command.
+struct Turn {
+    vm: VM,
+    /// What the game printed before the command
+    output: String,
+    room: Option<String>,
+    transcript: Option<Transcript>,
+    command: String,
+}
+
+struct Game {
+    program: Vec<isize>,
+    vm: VM,
+    status: Status,
+    /// What the game printed last
+    output: String,
+    /// The room the droid is in, if known
+    room: Option<String>,
+    history: Vec<Turn>,
+    /// Every command since the start of the game and its response, unless that can't be
+    /// replayed
+    transcript: Option<Transcript>,
+    /// The ship, explored on first use
+    graph: Option<Graph>,
+}
+
+fn slot_path(slot: &str) -> Result<PathBuf, String> {
+    if slot.is_empty()
+        || !slot
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid slot name {:?}", slot));
+    }
+    Ok(PathBuf::from(SAVE_DIR).join(format!("{}.save", slot)))
+}
+
+impl Game {
+    fn new(program: Vec<isize>) -> io::Result<Self> {
+        let mut game = Game {
+            vm: VM::init(program.clone()),
+            program,
+            status: Status::NeedsInput,
+            output: String::new(),
+            room: None,
+            history: Vec::new(),
+            transcript: Some(Transcript::new()),
+            graph: None,
+        };
+        game.play(None)?;
+        Ok(game)
+    }
+
+    /// Run until the game asks for a command, and print and record what it says.
+    fn play(&mut self, command: Option<&str>) -> io::Result<()> {
+        self.status = self.vm.run_limit(STEP_LIMIT);
+        self.output = read_ascii(&self.vm.read_all())?;
+        print!("{}", self.output);
+        if let Some(transcript) = &mut self.transcript {
+            append(transcript, command, &self.output, self.status.is_halted());
+        }
+        if let Some(room) = Response::parse(&self.output).room() {
+            self.room = Some(room.title.clone());
+        }
+        match self.status {
+            Status::Halted => println!("The game is over. Use :undo or :load to go back."),
+            Status::LimitReached | Status::InfiniteLoop(_) => {
+                self.transcript = None;
+                println!("The game is stuck in a loop. Use :undo or :load to go back.")
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        self.vm.write_port(&convert_ascii(command.as_bytes()));
+        self.vm.write_port(&[b'\n' as isize]);
+        self.play(Some(command))
+    }
+
+    /// Remember the current state, so `command` can be taken back.
+    fn checkpoint(&mut self, command: &str) {
+        self.history.push(Turn {
+            vm: self.vm.clone(),
+            output: self.output.clone(),
+            room: self.room.clone(),
+            transcript: self.transcript.clone(),
+            command: command.to_string(),
+        });
+    }
+
+    fn ready(&self) -> Result<(), String> {
+        if self.status.needs_input() {
+            Ok(())
+        } else {
+            Err("The game is not waiting for a command".to_string())
+        }
+    }
+
+    fn save(&self, slot: &str) -> Result<String, String> {
+        let path = slot_path(slot)?;
+        self.ready()?;
+        let mut data = String::new();
+        if let Some(room) = &self.room {
+            data.push_str(&format!("room {}\n", room));
+        }
+        data.push_str(&self.vm.snapshot());
+        fs::create_dir_all(SAVE_DIR)
+            .and_then(|_| fs::write(&path, data))
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        Ok(format!("Saved to {}", path.display()))
+    }
+
+    fn load(&mut self, slot: &str, command: &str) -> Result<String, String> {
+        let path = slot_path(slot)?;
+        let data = fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let (room, snapshot) = match data.strip_prefix("room ") {
+            Some(rest) => {
+                let (room, snapshot) = rest.split_once('\n').unwrap_or((rest, ""));
+                (Some(room.to_string()), snapshot)
+            }
+            None => (None, data.as_str()),
+        };
+        let vm = VM::restore(snapshot)?;
+        self.checkpoint(command);
+        self.vm = vm;
+        self.status = Status::NeedsInput;
+        self.output = String::new();
+        self.room = room;
+        self.transcript = None;
+        Ok(format!("Loaded {}", path.display()))
+    }
+
+    fn undo(&mut self) -> Result<String, String> {
+        let turn = self.history.pop().ok_or("Nothing to undo")?;
+        self.vm = turn.vm;
+        self.status = Status::NeedsInput;
+        self.output = turn.output;
+        self.room = turn.room;
+        self.transcript = turn.transcript;
+        print!("{}", self.output);
+        Ok(format!("Took back {:?}", turn.command))
+    }
+
+    /// Run `f` with the ship, explored on first use.
+    fn with_graph<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self, &Graph) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let graph = match self.graph.take() {
+            Some(graph) => graph,
+            None => {
+                println!("Exploring the ship...");
+                Graph::cached(&self.program, CACHE_DIR).map_err(|e| e.to_string())?
+            }
+        };
+        let result = f(self, &graph);
+        self.graph = Some(graph);
+        result
+    }
+
+    /// The items held, as listed by `inv` on a copy of the game.
+    fn inventory(&self, graph: &Graph) -> Result<BitBox, String> {
+        let mut vm = self.vm.clone();
+        vm.write_port(&convert_ascii(b"inv\n"));
+        vm.run_limit(STEP_LIMIT);
+        let output = read_ascii(&vm.read_all()).map_err(|e| e.to_string())?;
+        let response = Response::parse(&output);
+        let Some(Event::Inventory(names)) = response
+            .events
+            .iter()
+            .find(|event| matches!(event, Event::Inventory(_)))
+        else {
+            return Err(format!("No inventory in {:?}", output));
+        };
+        let mut held = bitbox![0; graph.items.len()];
+        for name in names {
+            held.set(find_item(graph, name)?, true);
+        }
+        Ok(held)
+    }
+
+    /// Follow `path`, stopping at the first command the game doesn't carry out.
+    fn follow(&mut self, path: &[Path]) -> Result<(), String> {
+        for step in path {
+            let command = step.to_string();
+            println!("> {}", command);
+            self.send(&command).map_err(|e| e.to_string())?;
+            self.ready()?;
+            let response = Response::parse(&self.output);
+            let failed = response.events.iter().any(|event| {
+                matches!(
+                    event,
+                    Event::CantGo | Event::Stuck | Event::NoSuchItem | Event::Unrecognized
+                )
+            });
+            if failed {
+                return Err(format!("Stopped after {:?}", command));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the autopilot starts from.
+    fn current_room(&self) -> Result<String, String> {
+        self.ready()?;
+        self.room
+            .clone()
+            .ok_or_else(|| "The current room is unknown, look around first".to_string())
+    }
+
+    fn goto(&mut self, graph: &Graph, to: &str, command: &str) -> Result<String, String> {
+        let from = self.current_room()?;
+        if !graph.nodes.contains_key(to) {
+            return Err(format!("Unknown room {:?}", to));
+        }
+        let path = graph
+            .goto(&from, to)
+            .ok_or_else(|| format!("No path from {} to {}", from, to))?;
+        self.checkpoint(command);
+        self.follow(&path)?;
+        Ok(format!("Arrived at {}", to))
+    }
+
+    fn collect(&mut self, graph: &Graph, names: &str, command: &str) -> Result<String, String> {
+        let from = self.current_room()?;
+        let mut wanted = bitbox![0; graph.items.len()];
+        if names.is_empty() {
+            wanted = graph.safe_items();
+        }
+        for name in names.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let idx = find_item(graph, name)?;
+            if let Some(hazard) = &graph.hazards[idx] {
+                return Err(format!("The {} is dangerous: {}", name, hazard));
+            }
+            wanted.set(idx, true);
+        }
+        let held = self.inventory(graph)?;
+        let path = graph
+            .collect_route(&from, &held, &wanted)
+            .ok_or("The items can't be reached")?;
+        self.checkpoint(command);
+        self.follow(&path)?;
+        Ok(format!("Collected {} items", wanted.count_ones()))
+    }
+
+    fn solve(&mut self, graph: &Graph, command: &str) -> Result<String, String> {
+        let from = self.current_room()?;
+        let checkpoint = graph.checkpoint().ok_or("No checkpoint in the ship")?;
+        let path = graph
+            .goto(&from, checkpoint.room)
+            .ok_or("The checkpoint can't be reached")?;
+        self.checkpoint(command);
+        self.follow(&path)?;
+
+        let held = self.inventory(graph)?;
+        let item_ids: Vec<usize> = held.iter_ones().collect();
+        let solution = solve_weight(item_ids.len(), |keep| {
+            let mut drop = bitbox![0; graph.items.len()];
+            for i in keep.iter_zeros() {
+                drop.set(item_ids[i], true);
+            }
+            let mut vm = self.vm.clone();
+            let (verdict, _response) = check_weight(&mut vm, graph, &drop)?;
+            Ok(verdict)
+        })
+        .map_err(|e| e.to_string())?
+        .map_err(|attempts| {
+            format!(
+                "No set of the items held gets through, after {} attempts",
+                attempts
+            )
+        })?;
+
+        // Do it for real
+        let drops: Vec<Path> = solution
+            .items
+            .iter_zeros()
+            .map(|i| Path::Drop(&graph.items[item_ids[i]]))
+            .collect();
+        self.follow(&drops)?;
+        println!("> {}", checkpoint.door);
+        self.send(checkpoint.door).map_err(|e| e.to_string())?;
+        if Response::parse(&self.output).verdict() != Some(Verdict::Accepted) {
+            return Err("The floor changed its mind".to_string());
+        }
+        Ok(format!("Found after {} attempts", solution.attempts))
+    }
+
+    fn meta(&mut self, line: &str) -> Result<String, String> {
+        let meta = &line[1..];
+        let (command, arg) = meta.split_once(' ').unwrap_or((meta, ""));
+        let arg = arg.trim();
+        match command {
+            "save" => self.save(arg),
+            "load" => self.load(arg, line),
+            "undo" => self.undo(),
+            "history" => Ok(self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, turn)| format!("{:4}  {}", i + 1, turn.command))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "goto" => self.with_graph(|game, graph| game.goto(graph, arg, line)),
+            "collect" => self.with_graph(|game, graph| game.collect(graph, arg, line)),
+            "solve" => self.with_graph(|game, graph| game.solve(graph, line)),
+            "help" => Ok(HELP.to_string()),
+            _ => Err(format!("Unknown meta-command {:?}, try :help", command)),
+        }
+    }
+}
+
+fn find_item(graph: &Graph, name: &str) -> Result<usize, String> {
+    graph
+        .items
+        .iter()
+        .position(|item| item == name)
+        .ok_or_else(|| format!("Unknown item {:?}", name))
+}
+
 fn main() -> io::Result<()> {
     env_logger::init();
 
+    let mut args = Args::new(USAGE);
+    let mut record = None;
+    let mut replay_file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            "-r" | "--record" => {
+                record = Some(args.value(&arg)?);
+            }
+            "-p" | "--replay" => {
+                replay_file = Some(args.value(&arg)?);
+            }
+            _ => return Err(Args::unknown(&arg)),
+        }
+    }
+
     let input = std::fs::read_to_string("input.txt").unwrap();
     let program = parse_program(&input);
 
-    let mut vm = VM::init(program);
+    if let Some(path) = replay_file {
+        let transcript = read_transcript(&path)?;
+        match replay(&program, &transcript) {
+            Ok(()) => {
+                // The first exchange is the opening room, before any command
+                let commands = transcript
+                    .exchanges
+                    .iter()
+                    .filter(|ex| !ex.input.is_empty())
+                    .count();
+                println!("{}: {} commands replayed", path, commands);
+                return Ok(());
+            }
+            Err(mismatch) => {
+                println!("{}: {}", path, mismatch);
+                process::exit(1);
+            }
+        }
+    }
+
     let mut rl = rustyline::DefaultEditor::new().map_err(rl_error)?;
+    let mut game = Game::new(program)?;
 
-    while !vm.run().is_ready() {
-        write_console(&vm.read_all())?;
-        let readline = rl.readline("> ").map_err(rl_error)?;
-        vm.write_port(&convert_ascii(readline.as_bytes()));
-        vm.write_port(&[b'\n' as isize]);
+    loop {
+        let line = match rl.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => break,
+            Err(e) => return Err(rl_error(e)),
+        };
+        let _ = rl.add_history_entry(line.as_str());
+        if line.starts_with(':') {
+            match game.meta(&line) {
+                Ok(msg) if msg.is_empty() => {}
+                Ok(msg) => println!("{}", msg),
+                Err(e) => println!("Error: {}", e),
+            }
+            continue;
+        }
+        if let Err(e) = game.ready() {
+            println!("Error: {}", e);
+            continue;
+        }
+        game.checkpoint(&line);
+        game.send(&line)?;
     }
 
+    if let Some(path) = record {
+        match &game.transcript {
+            Some(transcript) => write_transcript(&path, transcript)?,
+            None => println!("Error: this game can't be replayed, {} not written", path),
+        }
+    }
     Ok(())
 }
@@ -1,14 +1,79 @@
 use std::io;
 
 use day25::*;
-use intcode::parse_program;
+use intcode::{
+    cli::{Args, invalid_input},
+    parse_program,
+};
+
+static USAGE: &str = "\
+Usage: scan [OPTIONS]
+
+Explore the ship in input.txt, and print its rooms.
+
+Options:
+  -f, --format <FORMAT>  Output format: text, dot, map or graph [default: text]
+  -n, --no-cache         Explore the ship again instead of using the graph
+                         cached in cache/
+  -h, --help             Print this help
+
+The text format lists the path from the start and the items of each room.
+The dot format is a Graphviz graph, and the map format lays the rooms out
+on a grid. The graph format is the one of the cache, sorted so that the
+ships of two inputs can be diffed.";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Dot,
+    Map,
+    Graph,
+}
 
 fn main() -> io::Result<()> {
     env_logger::init();
 
+    let mut args = Args::new(USAGE);
+    let mut format = Format::Text;
+    let mut cache = true;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            "-f" | "--format" => {
+                format = match args.value(&arg)?.as_str() {
+                    "text" => Format::Text,
+                    "dot" => Format::Dot,
+                    "map" => Format::Map,
+                    "graph" => Format::Graph,
+                    f => return Err(invalid_input(format!("Unknown format {:?}", f))),
+                };
+            }
+            "-n" | "--no-cache" => {
+                cache = false;
+            }
+            _ => return Err(Args::unknown(&arg)),
+        }
+    }
+
     let input = std::fs::read_to_string("input.txt").unwrap();
     let program = parse_program(&input);
-    let graph = Graph::scan(program)?;
-    graph.search();
+    let graph = if cache {
+        Graph::cached(&program, CACHE_DIR)?
+    } else {
+        Graph::scan(program)?
+    };
+    match format {
+        Format::Text => {
+            graph.search();
+            for (item, hazard) in graph.items.iter().zip(&graph.hazards) {
+                if let Some(hazard) = hazard {
+                    println!("Dangerous item {}: {}", item, hazard);
+                }
+            }
+        }
+        Format::Dot => print!("{}", graph.to_dot()),
+        Format::Map => print!("{}", graph.to_ascii_map()),
+        Format::Graph => print!("{}", graph),
+    }
     Ok(())
 }
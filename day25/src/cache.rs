@@ -0,0 +1,260 @@
+use std::{collections::HashMap, fmt, fs, io, path::Path, str::FromStr};
+
+use crate::{Graph, Hazard};
+
+/// Directory of the cached graphs, relative to the working directory
+pub static CACHE_DIR: &str = "cache";
+
+/// Version of the cached graphs. Bump it when the layout of the files or the classification
+/// of the items changes, so that the old files are scanned again.
+pub const GRAPH_VERSION: u32 = 1;
+
+/// FNV-1a hash of a program, stable across runs and toolchains.
+pub fn program_hash(program: &[isize]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &v in program {
+        for b in (v as i64).to_le_bytes() {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+impl Graph {
+    /// Load the graph of `program` from `dir`, or scan the ship and save it there. A file
+    /// of another [`GRAPH_VERSION`] is scanned again.
+    pub fn cached(program: &[isize], dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let path = dir.join(format!("graph-{:016x}.txt", program_hash(program)));
+        if let Ok(text) = fs::read_to_string(&path) {
+            match text.parse() {
+                Ok(graph) => return Ok(graph),
+                Err(e) => log::warn!("Ignoring {}: {}", path.display(), e),
+            }
+        }
+        let graph = Graph::scan(program.to_vec())?;
+        fs::create_dir_all(dir)?;
+        fs::write(&path, graph.to_string())?;
+        Ok(graph)
+    }
+}
+
+/// A version line, then one line per room followed by its items and doors, in sorted order
+/// so that two graphs can be diffed:
+///
+/// ```text
+/// version 1
+/// start Hull Breach
+/// room Holodeck
+/// item giant electromagnet | stuck
+/// door east | Warp Drive Maintenance
+/// ```
+///
+/// An item is followed by `| stuck`, `| loop` or `| fatal | <last words>` when it is
+/// dangerous, with `\n` and `\\` escaped in the last words.
+impl fmt::Display for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "version {}", GRAPH_VERSION)?;
+        writeln!(f, "start {}", self.start)?;
+        let mut rooms: Vec<&String> = self.nodes.keys().collect();
+        rooms.sort();
+        for room in rooms {
+            writeln!(f, "room {}", room)?;
+            let mut items = self.nodes[room].clone();
+            items.sort_by_key(|&idx| &self.items[idx]);
+            for idx in items {
+                write!(f, "item {}", self.items[idx])?;
+                match &self.hazards[idx] {
+                    None => writeln!(f)?,
+                    Some(Hazard::Stuck) => writeln!(f, " | stuck")?,
+                    Some(Hazard::Loop) => writeln!(f, " | loop")?,
+                    Some(Hazard::Fatal(words)) => writeln!(f, " | fatal | {}", escape(words))?,
+                }
+            }
+            let mut doors: Vec<(&String, &String)> =
+                self.edges.get(room).into_iter().flatten().collect();
+            doors.sort();
+            for (door, next) in doors {
+                writeln!(f, "door {} | {}", door, next)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Rooms, and items with their rooms, in sorted order.
+#[allow(clippy::type_complexity)]
+fn contents(graph: &Graph) -> (Vec<&String>, Vec<(&String, &String, &Option<Hazard>)>) {
+    let mut rooms: Vec<&String> = graph.nodes.keys().collect();
+    rooms.sort();
+    let mut items: Vec<_> = graph
+        .nodes
+        .iter()
+        .flat_map(|(room, items)| {
+            items
+                .iter()
+                .map(move |&idx| (room, &graph.items[idx], &graph.hazards[idx]))
+        })
+        .collect();
+    items.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+    (rooms, items)
+}
+
+/// Graphs are equal when they have the same rooms, doors and items, whatever the order of
+/// the items, so that a graph read from the cache is equal to the one scanned.
+impl PartialEq for Graph {
+    fn eq(&self, other: &Self) -> bool {
+        self.start == other.start && self.edges == other.edges && contents(self) == contents(other)
+    }
+}
+
+impl FromStr for Graph {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut graph = Graph {
+            start: String::new(),
+            items: Vec::new(),
+            hazards: Vec::new(),
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+        };
+        let mut room: Option<String> = None;
+        let mut version = None;
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Line {}: invalid line {:?}", i + 1, line);
+            let (keyword, arg) = line.split_once(' ').ok_or_else(invalid)?;
+            let fields: Vec<&str> = arg.split(" | ").collect();
+            match (keyword, &fields[..]) {
+                ("version", [v]) => version = Some(v.parse::<u32>().map_err(|_| invalid())?),
+                ("start", [start]) => graph.start = start.to_string(),
+                ("room", [title]) => {
+                    graph.nodes.insert(title.to_string(), Vec::new());
+                    room = Some(title.to_string());
+                }
+                ("item", [name, hazard @ ..]) => {
+                    let hazard = match hazard {
+                        [] => None,
+                        ["stuck"] => Some(Hazard::Stuck),
+                        ["loop"] => Some(Hazard::Loop),
+                        ["fatal", words @ ..] => Some(Hazard::Fatal(unescape(&words.join(" | ")))),
+                        _ => return Err(invalid()),
+                    };
+                    let room = room.as_ref().ok_or_else(invalid)?;
+                    graph.nodes.get_mut(room).unwrap().push(graph.items.len());
+                    graph.items.push(name.to_string());
+                    graph.hazards.push(hazard);
+                }
+                ("door", [door, next]) => {
+                    let room = room.clone().ok_or_else(invalid)?;
+                    graph
+                        .edges
+                        .entry(room)
+                        .or_default()
+                        .insert(door.to_string(), next.to_string());
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if version != Some(GRAPH_VERSION) {
+            return Err(format!(
+                "Version {:?} instead of {}",
+                version, GRAPH_VERSION
+            ));
+        }
+        if !graph.nodes.contains_key(&graph.start) {
+            return Err(format!("Unknown start room {:?}", graph.start));
+        }
+        Ok(graph)
+    }
+}
+
+#[test]
+fn test_graph_text() {
+    let text = "\
+version 1
+start Hull Breach
+room Holodeck
+item giant electromagnet | stuck
+item mug
+door south | Hull Breach
+room Hull Breach
+door north | Holodeck
+room Kitchen
+item escape pod | fatal | You're launched into space! Bye!
+";
+    let graph: Graph = text.parse().unwrap();
+    assert_eq!(graph.items, ["giant electromagnet", "mug", "escape pod"]);
+    assert_eq!(graph.hazards, [
+        Some(Hazard::Stuck),
+        None,
+        Some(Hazard::Fatal(
+            "You're launched into space! Bye!".to_string()
+        ))
+    ]);
+    assert_eq!(graph.edges["Hull Breach"]["north"], "Holodeck");
+    assert_eq!(graph.to_string(), text);
+
+    assert!("version 1\nstart Nowhere\n".parse::<Graph>().is_err());
+    assert!(
+        "version 1\nstart A\nitem mug\nroom A\n"
+            .parse::<Graph>()
+            .is_err()
+    );
+    // Files of another version are scanned again
+    let old = text.replace("version 1", "version 0");
+    assert!(old.parse::<Graph>().is_err());
+    assert!(
+        text.lines()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .parse::<Graph>()
+            .is_err()
+    );
+
+    // Last words over several lines, or with the separator, survive the round trip
+    let mut graph = graph;
+    graph.hazards[2] = Some(Hazard::Fatal(
+        "You're launched into space!\nBye! | C:\\pod\\n".to_string(),
+    ));
+    let text = graph.to_string();
+    assert!(text.contains("| fatal | You're launched into space!\\nBye! | C:\\\\pod\\\\n\n"));
+    assert!(text.parse::<Graph>().unwrap() == graph);
+    // Whatever the order of the items
+    let mut shuffled: Graph = text.parse().unwrap();
+    shuffled.items.swap(0, 1);
+    shuffled.hazards.swap(0, 1);
+    *shuffled.nodes.get_mut("Holodeck").unwrap() = vec![1, 0];
+    assert!(shuffled == graph);
+    shuffled.hazards[1] = None;
+    assert!(shuffled != graph);
+    assert_ne!(program_hash(&[1, 2, 3]), program_hash(&[1, 2, 4]));
+}
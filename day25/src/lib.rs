@@ -1,23 +1,56 @@
-#![feature(map_try_insert)]
 #![feature(iter_intersperse)]
 
 use std::{
     collections::{HashMap, VecDeque},
+    fmt,
     io::{self, Write},
     string::FromUtf8Error,
 };
 
-use bitvec::{bitbox, boxed::BitBox, slice::BitSlice};
-use intcode::VM;
+use bitvec::{boxed::BitBox, slice::BitSlice};
+use intcode::{Status, VM};
 use log::log_enabled;
 
-pub static ITEM_EXCEPTION: &'static [&str] = &[
-    "infinite loop",
-    "giant electromagnet",
-    "escape pod",
-    "photons",
-    "molten lava",
-];
+mod cache;
+mod map;
+mod plan;
+mod record;
+mod response;
+mod solve;
+mod weight;
+
+pub use cache::{CACHE_DIR, GRAPH_VERSION, program_hash};
+pub use record::{
+    Recording, append, read_transcript, replay, start_recording, unrecorded, write_transcript,
+};
+pub use response::{Event, Response, Room, Verdict};
+pub use solve::{Answer, SolveError, solve};
+pub use weight::{Solution, solve_weight};
+
+/// Steps a command may take before the game is considered stuck in a loop. Some loops keep
+/// printing, so they can't be caught by the VM's loop detection.
+pub const STEP_LIMIT: usize = 1_000_000;
+
+/// What happens to the droid when it picks up a dangerous item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hazard {
+    /// The game ends, with these last words
+    Fatal(String),
+    /// The game never asks for the next command
+    Loop,
+    /// The droid can no longer move
+    Stuck,
+}
+
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hazard::Fatal(words) => write!(f, "fatal: {}", words),
+            Hazard::Loop => write!(f, "infinite loop"),
+            Hazard::Stuck => write!(f, "can no longer move"),
+        }
+    }
+}
 
 pub fn utf8_error(e: FromUtf8Error) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidData, e)
@@ -57,13 +90,14 @@ pub fn run_vm(vm: &mut VM, command: &str) -> io::Result<String> {
     log::info!("{:?}", command);
     vm.write_port(&convert_ascii(command.as_bytes()));
     vm.write_port(&[b'\n' as isize]);
-    let exit = vm.run().is_ready();
+    let exit = vm.run().is_halted();
     let output = read_ascii(&vm.read_all())?;
     if log_enabled!(log::Level::Info) {
         for line in output.lines() {
             log::info!("{}", line);
         }
     }
+    record::record(command, &output, exit);
     if exit {
         log::info!("HALT");
         Err(io::Error::new(io::ErrorKind::BrokenPipe, "HALT"))
@@ -76,13 +110,14 @@ pub fn run_vm_may_halt(vm: &mut VM, command: &str) -> io::Result<(String, bool)>
     log::info!("{:?}", command);
     vm.write_port(&convert_ascii(command.as_bytes()));
     vm.write_port(&[b'\n' as isize]);
-    let exit = vm.run().is_ready();
+    let exit = vm.run().is_halted();
     let output = read_ascii(&vm.read_all())?;
     if log_enabled!(log::Level::Info) {
         for line in output.lines() {
             log::info!("{}", line);
         }
     }
+    record::record(command, &output, exit);
     if exit {
         log::info!("HALT");
     }
@@ -99,11 +134,23 @@ pub struct Vertex<'r> {
 pub enum Path<'r> {
     Door(&'r str),
     Take(&'r str),
+    Drop(&'r str),
+}
+
+/// The room guarding the pressure-sensitive floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint<'r> {
+    pub room: &'r str,
+    /// The door to the floor
+    pub door: &'r str,
+    pub floor: &'r str,
 }
 
 pub struct Graph {
     pub start: String,
     pub items: Vec<String>,
+    /// What picking up each item does, `None` for the safe ones
+    pub hazards: Vec<Option<Hazard>>,
     pub nodes: HashMap<String, Vec<usize>>,
     pub edges: HashMap<String, HashMap<String, String>>,
 }
@@ -131,25 +178,34 @@ impl Graph {
                 start = title.clone();
             }
 
-            if node_idx.try_insert(title.clone(), node.clone()).is_err() {
+            if node_idx.contains_key(&title) {
                 continue;
             }
+            let hazards = node
+                .items
+                .iter()
+                .map(|item| classify_item(&vm, item, node.doors.first()))
+                .collect::<io::Result<Vec<_>>>()?;
+            node_idx.insert(title.clone(), (node.clone(), hazards));
             for (door, vm) in explore_node(vm, node.doors) {
                 queue.push_back((Some((title.clone(), door)), vm));
             }
         }
 
         let mut items = Vec::new();
+        let mut item_hazards = Vec::new();
 
         let nodes = node_idx
             .into_iter()
-            .map(|(title, node)| {
+            .map(|(title, (node, hazards))| {
                 let node_items = node
                     .items
                     .into_iter()
-                    .map(|item| {
+                    .zip(hazards)
+                    .map(|(item, hazard)| {
                         let idx = items.len();
                         items.push(item);
+                        item_hazards.push(hazard);
                         idx
                     })
                     .collect::<Vec<_>>();
@@ -160,11 +216,30 @@ impl Graph {
         Ok(Self {
             start,
             items,
+            hazards: item_hazards,
             nodes,
             edges,
         })
     }
 
+    /// The checkpoint, found as the room with a door to a room that ejected the droid. As the
+    /// doors of that room couldn't be explored, it is the only one without any.
+    pub fn checkpoint(&self) -> Option<Checkpoint<'_>> {
+        let mut rooms: Vec<&String> = self.edges.keys().collect();
+        rooms.sort();
+        rooms.into_iter().find_map(|room| {
+            let (door, floor) = self.edges[room]
+                .iter()
+                .find(|(_, next)| self.edges.get(*next).is_none_or(HashMap::is_empty))?;
+            Some(Checkpoint { room, door, floor })
+        })
+    }
+
+    /// The items that can be picked up without harm.
+    pub fn safe_items(&self) -> BitBox {
+        self.hazards.iter().map(|hazard| hazard.is_none()).collect()
+    }
+
     pub fn search(&self) {
         let mut path: HashMap<&String, String> = HashMap::new();
         let mut queue = VecDeque::new();
@@ -200,75 +275,86 @@ impl Graph {
             );
         }
     }
+}
 
-    pub fn collect_items<'r>(&'r self, target: &Vertex<'r>) -> Option<Vec<Path<'r>>> {
-        let mut queue: VecDeque<Vertex<'r>> = VecDeque::new();
-        let mut path: HashMap<Vertex<'r>, Vec<Path<'r>>> = HashMap::new();
-        let i_empty = bitbox![0; self.items.len()];
-        let (i0, take) = self.add_items(self.start.as_str(), &target.items, &i_empty);
-        let v0 = Vertex {
-            node: &self.start,
-            items: i0,
+#[cfg(test)]
+impl Graph {
+    /// A ship without items, starting in the first room of `edges`. Each `(room, door, next)`
+    /// also gets the door back.
+    fn from_edges(edges: &[(&str, &str, &str)]) -> Self {
+        let back = |door| match door {
+            "north" => "south",
+            "south" => "north",
+            "east" => "west",
+            "west" => "east",
+            _ => panic!("Unknown door {}", door),
         };
-        path.insert(v0.clone(), take.into_iter().map(Path::Take).collect());
-        queue.push_back(v0);
-
-        while let Some(v0) = queue.pop_front() {
-            let p0 = path.get(&v0).unwrap().clone();
-            if &v0 == target {
-                return Some(p0.clone());
-            }
-
-            if let Some(edges) = self.edges.get(v0.node) {
-                for (door, n1) in edges {
-                    let (i1, take) = self.add_items(n1.as_str(), &target.items, &v0.items);
-                    let v1 = Vertex {
-                        node: n1,
-                        items: i1,
-                    };
-                    if path.get(&v1).is_none() {
-                        let mut p1 = p0.clone();
-                        p1.push(Path::Door(door));
-                        p1.extend(take.into_iter().map(Path::Take));
-                        path.insert(v1.clone(), p1);
-                        queue.push_back(v1);
-                    }
-                }
-            }
-        }
-
-        None
-    }
-
-    fn add_items<'r>(
-        &'r self,
-        node: &str,
-        target: &BitSlice,
-        collected: &BitSlice,
-    ) -> (BitBox, Vec<&'r str>) {
-        let mut collected = BitBox::from_bitslice(collected);
-        let mut path = vec![];
-        if let Some(items) = self.nodes.get(node) {
-            for &item in items {
-                if target[item] {
-                    collected.set(item, true);
-                    path.push(self.items[item].as_str());
-                }
+        let mut graph = Graph {
+            start: edges[0].0.to_string(),
+            items: vec![],
+            hazards: vec![],
+            nodes: HashMap::new(),
+            edges: HashMap::new(),
+        };
+        for &(room, door, next) in edges {
+            for (a, d, b) in [(room, door, next), (next, back(door), room)] {
+                graph.nodes.entry(a.to_string()).or_default();
+                graph
+                    .edges
+                    .entry(a.to_string())
+                    .or_default()
+                    .insert(d.to_string(), b.to_string());
             }
         }
-        (collected, path)
+        graph
     }
 }
 
-fn read_node(mut vm: VM) -> io::Result<(VM, Node)> {
-    if vm.run().is_ready() {
+fn read_node(mut vm: VM) -> io::Result<(VM, Room)> {
+    if vm.run().is_halted() {
         return Err(io::Error::new(
             io::ErrorKind::BrokenPipe,
             "Program finished",
         ));
     }
-    let node = Node::parse(&read_ascii(&vm.read_all())?)?;
-    Ok((vm, node))
+    let output = read_ascii(&vm.read_all())?;
+    let response = Response::parse(&output);
+    let mut room = response.rooms().next().cloned().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No room in {:?}", output),
+        )
+    })?;
+    // Sent back to the checkpoint, so the doors of this room can't be explored
+    if response.ejected() {
+        room.doors.clear();
+    }
+    Ok((vm, room))
+}
+
+/// Pick up `item` on a fork of `vm`, then try to leave through `door`.
+fn classify_item(vm: &VM, item: &str, door: Option<&String>) -> io::Result<Option<Hazard>> {
+    let mut vm = vm.clone();
+    vm.enable_loop_detection();
+    let mut send = |command: &str| -> io::Result<Option<Hazard>> {
+        vm.write_port(&convert_ascii(command.as_bytes()));
+        vm.write_port(&[b'\n' as isize]);
+        let status = vm.run_limit(STEP_LIMIT);
+        let response = Response::parse(&read_ascii(&vm.read_all())?);
+        Ok(match status {
+            Status::Halted => Some(Hazard::Fatal(
+                response.game_over().unwrap_or_default().to_string(),
+            )),
+            Status::InfiniteLoop(_) | Status::LimitReached => Some(Hazard::Loop),
+            _ if response.events.contains(&Event::Stuck) => Some(Hazard::Stuck),
+            _ => None,
+        })
+    };
+    let hazard = send(&format!("take {}", item))?;
+    match door {
+        Some(door) if hazard.is_none() => send(door),
+        _ => Ok(hazard),
+    }
 }
 
 fn explore_node(vm: VM, doors: Vec<String>) -> Vec<(String, VM)> {
@@ -283,64 +369,28 @@ fn explore_node(vm: VM, doors: Vec<String>) -> Vec<(String, VM)> {
         .collect()
 }
 
-#[derive(Debug, Clone)]
-struct Node {
-    title: String,
-    doors: Vec<String>,
-    items: Vec<String>,
-}
-
-impl Node {
-    fn parse(output: &str) -> io::Result<Self> {
-        let mut doors = Vec::new();
-        let mut items = Vec::new();
-        let mut title = String::new();
-
-        let mut phase = 0;
-        for line in output.lines() {
-            if line.trim().is_empty() || line.starts_with("Command?") {
-                continue;
-            }
-            if line.starts_with("==") && line.ends_with("==") {
-                title = line[3..line.len() - 3].to_string();
-                if title == "Pressure-Sensitive Floor" {
-                    return Ok(Node {
-                        title,
-                        doors: vec![],
-                        items: vec![],
-                    });
-                }
-                continue;
-            }
-            if line.starts_with("Doors here lead:") {
-                phase = 1;
-                continue;
-            }
-            if line.starts_with("Items here:") {
-                phase = 2;
-                continue;
-            }
-            match phase {
-                0 => {}
-                1 if line.starts_with("- ") => {
-                    doors.push(line[2..].to_string());
-                }
-                2 if line.starts_with("- ") => {
-                    items.push(line[2..].to_string());
-                }
-                _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("Invalid input {:?} at {:?}", line, title),
-                    ));
-                }
-            }
-        }
-
-        Ok(Self {
-            title,
-            doors,
-            items,
-        })
+/// Drop `items` at the checkpoint, and step on the pressure-sensitive floor.
+pub fn check_weight(
+    vm: &mut VM,
+    graph: &Graph,
+    items: &BitSlice,
+) -> io::Result<(Verdict, Response)> {
+    for idx in items.iter_ones() {
+        let item = &graph.items[idx];
+        run_vm(vm, &format!("drop {}", item))?;
     }
+
+    let checkpoint = graph
+        .checkpoint()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No checkpoint in the ship"))?;
+    let (ascii_output, _exit) = run_vm_may_halt(vm, checkpoint.door)?;
+
+    let response = Response::parse(&ascii_output);
+    let verdict = response.verdict().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("analysis not found: {}", ascii_output),
+        )
+    })?;
+    Ok((verdict, response))
 }
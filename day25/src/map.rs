@@ -0,0 +1,310 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Write,
+};
+
+use crate::Graph;
+
+fn offset(door: &str) -> Option<(isize, isize)> {
+    match door {
+        "north" => Some((0, -1)),
+        "south" => Some((0, 1)),
+        "east" => Some((1, 0)),
+        "west" => Some((-1, 0)),
+        _ => None,
+    }
+}
+
+fn sorted<K: Ord, V>(map: &HashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+impl Graph {
+    fn item_labels(&self, room: &str) -> Vec<String> {
+        let Some(items) = self.nodes.get(room) else {
+            return vec![];
+        };
+        items
+            .iter()
+            .map(|&idx| match &self.hazards[idx] {
+                Some(hazard) => format!("{} ({})", self.items[idx], hazard),
+                None => self.items[idx].clone(),
+            })
+            .collect()
+    }
+
+    /// Graphviz graph of the rooms, with one edge per door.
+    pub fn to_dot(&self) -> String {
+        let checkpoint = self.checkpoint().map(|c| c.room);
+        let mut dot = String::from("digraph ship {\n");
+        for (room, _) in sorted(&self.nodes) {
+            let mut label = room.clone();
+            for item in self.item_labels(room) {
+                label.push('\n');
+                label.push_str(&item);
+            }
+            let mut attrs = vec![format!("label={:?}", label)];
+            if *room == self.start {
+                attrs.push("shape=doublecircle".to_string());
+            } else if Some(room.as_str()) == checkpoint {
+                attrs.push("shape=box".to_string());
+                attrs.push("style=bold".to_string());
+            }
+            let dangerous = self.nodes[room]
+                .iter()
+                .any(|&idx| self.hazards[idx].is_some());
+            if dangerous {
+                attrs.push("color=red".to_string());
+            }
+            writeln!(dot, "  {:?} [{}];", room, attrs.join(", ")).unwrap();
+        }
+        for (room, doors) in sorted(&self.edges) {
+            for (door, next) in sorted(doors) {
+                writeln!(dot, "  {:?} -> {:?} [label={:?}];", room, next, door).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Grid position of each room, following the doors from the start. A room that would
+    /// overlap a room already placed is moved further in the same direction.
+    fn layout(&self) -> HashMap<&str, (isize, isize)> {
+        let mut pos = HashMap::new();
+        let mut taken = HashMap::new();
+        let mut queue = VecDeque::new();
+        pos.insert(self.start.as_str(), (0, 0));
+        taken.insert((0, 0), self.start.as_str());
+        queue.push_back(self.start.as_str());
+        while let Some(room) = queue.pop_front() {
+            let (x, y) = pos[room];
+            let Some(doors) = self.edges.get(room) else {
+                continue;
+            };
+            for (door, next) in sorted(doors) {
+                let Some((dx, dy)) = offset(door) else {
+                    continue;
+                };
+                if pos.contains_key(next.as_str()) {
+                    continue;
+                }
+                // The ship isn't laid out on a grid, so move on to the next free cell
+                let mut p = (x + dx, y + dy);
+                while taken.contains_key(&p) {
+                    p = (p.0 + dx, p.1 + dy);
+                }
+                pos.insert(next.as_str(), p);
+                taken.insert(p, next.as_str());
+                queue.push_back(next.as_str());
+            }
+        }
+        pos
+    }
+
+    /// Text map of the rooms on a grid, followed by a legend. Each room is drawn as `[Lm]`
+    /// with its letter `L` and a mark `m`: `S` for the start, `C` for the checkpoint, `*` for
+    /// a room with items and `!` for a room with a dangerous item. Only the doors between
+    /// neighbours on the grid are drawn, the others are listed after the legend. The letters
+    /// skip `S` and `C`, so a room is never mistaken for the start or the checkpoint.
+    pub fn to_ascii_map(&self) -> String {
+        let pos = self.layout();
+        let checkpoint = self.checkpoint().map(|c| c.room);
+        let (min_x, max_x, min_y, max_y) =
+            pos.values()
+                .fold((0, 0, 0, 0), |(min_x, max_x, min_y, max_y), &(x, y)| {
+                    (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y))
+                });
+
+        // Letters in reading order, other than the marks
+        let by_pos: BTreeMap<(isize, isize), &str> =
+            pos.iter().map(|(&room, &(x, y))| ((y, x), room)).collect();
+        let letters: HashMap<&str, char> = by_pos
+            .values()
+            .zip(
+                ('A'..='Z')
+                    .chain('a'..='z')
+                    .filter(|c| !matches!(c, 'S' | 'C')),
+            )
+            .map(|(&room, letter)| (room, letter))
+            .collect();
+
+        let width = ((max_x - min_x + 1) * 5) as usize;
+        let height = ((max_y - min_y + 1) * 2) as usize;
+        let mut grid = vec![vec![' '; width]; height];
+        for (&room, &(x, y)) in &pos {
+            let col = ((x - min_x) * 5) as usize;
+            let row = ((y - min_y) * 2) as usize;
+            let items = self.nodes.get(room).map(Vec::as_slice).unwrap_or(&[]);
+            let mark = if room == self.start {
+                'S'
+            } else if Some(room) == checkpoint {
+                'C'
+            } else if items.iter().any(|&idx| self.hazards[idx].is_some()) {
+                '!'
+            } else if !items.is_empty() {
+                '*'
+            } else {
+                ' '
+            };
+            let letter = letters.get(room).copied().unwrap_or('?');
+            grid[row][col..col + 4].copy_from_slice(&['[', letter, mark, ']']);
+        }
+        // Doors between neighbours, drawn in the gap between their boxes
+        let mut off_grid: Vec<(&str, &str, &str)> = Vec::new();
+        for (room, doors) in sorted(&self.edges) {
+            let Some(&(x, y)) = pos.get(room.as_str()) else {
+                continue;
+            };
+            for (door, next) in sorted(doors) {
+                let neighbour = offset(door)
+                    .is_some_and(|(dx, dy)| pos.get(next.as_str()) == Some(&(x + dx, y + dy)));
+                if !neighbour {
+                    // Listed once per pair of rooms
+                    let listed = off_grid
+                        .iter()
+                        .any(|&(r, _, n)| r == next.as_str() && n == room.as_str());
+                    if !listed {
+                        off_grid.push((room, door, next));
+                    }
+                    continue;
+                }
+                let col = ((x - min_x) * 5) as usize;
+                let row = ((y - min_y) * 2) as usize;
+                match door.as_str() {
+                    "east" => grid[row][col + 4] = '-',
+                    "west" => grid[row][col - 1] = '-',
+                    "south" => grid[row + 1][col + 1] = '|',
+                    _ => grid[row - 1][col + 1] = '|',
+                }
+            }
+        }
+
+        let mut map = String::new();
+        for line in grid {
+            let line: String = line.into_iter().collect();
+            let line = line.trim_end();
+            if !line.is_empty() {
+                map.push_str(line);
+                map.push('\n');
+            }
+        }
+        map.push('\n');
+        for &room in by_pos.values() {
+            let mut entry = format!("{} {}", letters[room], room);
+            if room == self.start {
+                entry.push_str(" (start)");
+            } else if Some(room) == checkpoint {
+                entry.push_str(" (checkpoint)");
+            }
+            let items = self.item_labels(room);
+            if !items.is_empty() {
+                write!(entry, ": {}", items.join(", ")).unwrap();
+            }
+            map.push_str(&entry);
+            map.push('\n');
+        }
+        for (room, door, next) in off_grid {
+            writeln!(map, "{} {} -> {}", room, door, next).unwrap();
+        }
+        map
+    }
+}
+
+#[test]
+fn test_export() {
+    use crate::Hazard;
+
+    let edges = [
+        ("Hull Breach", "north", "Holodeck"),
+        ("Holodeck", "south", "Hull Breach"),
+        ("Hull Breach", "east", "Security Checkpoint"),
+        ("Security Checkpoint", "west", "Hull Breach"),
+        ("Security Checkpoint", "east", "Pressure-Sensitive Floor"),
+    ];
+    let mut graph = Graph {
+        start: "Hull Breach".to_string(),
+        items: vec!["escape pod".to_string(), "mug".to_string()],
+        hazards: vec![Some(Hazard::Loop), None],
+        nodes: HashMap::from([
+            ("Hull Breach".to_string(), vec![]),
+            ("Holodeck".to_string(), vec![0, 1]),
+            ("Security Checkpoint".to_string(), vec![]),
+            ("Pressure-Sensitive Floor".to_string(), vec![]),
+        ]),
+        edges: HashMap::new(),
+    };
+    for (room, door, next) in edges {
+        graph
+            .edges
+            .entry(room.to_string())
+            .or_default()
+            .insert(door.to_string(), next.to_string());
+    }
+
+    assert_eq!(
+        graph.to_ascii_map(),
+        "\
+[A!]
+ |
+[BS]-[DC]-[E ]
+
+A Holodeck: escape pod (infinite loop), mug
+B Hull Breach (start)
+D Security Checkpoint (checkpoint)
+E Pressure-Sensitive Floor
+"
+    );
+
+    // The Engine doesn't fit east of the Cellar, so that door can't be drawn without
+    // crossing the Deck
+    let moved = Graph::from_edges(&[
+        ("Arcade", "east", "Bridge"),
+        ("Arcade", "south", "Cellar"),
+        ("Bridge", "south", "Deck"),
+        ("Cellar", "east", "Engine"),
+    ]);
+    let map = moved.to_ascii_map();
+    assert_eq!(
+        map,
+        "\
+[AS]-[B ]
+ |    |
+[D ] [E ] [F ]
+
+A Arcade (start)
+B Bridge
+D Cellar
+E Deck
+F Engine
+Cellar east -> Engine
+"
+    );
+    // Every line joins two boxes next to each other
+    let grid: Vec<Vec<char>> = map
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .map(|line| line.chars().collect())
+        .collect();
+    for (row, line) in grid.iter().enumerate() {
+        for (col, &c) in line.iter().enumerate() {
+            match c {
+                '-' => assert_eq!((line[col - 1], line[col + 1]), (']', '[')),
+                '|' => assert_eq!((grid[row - 1][col - 1], grid[row + 1][col - 1]), ('[', '[')),
+                _ => {}
+            }
+        }
+    }
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph ship {\n"));
+    assert!(dot.contains(
+        "  \"Holodeck\" [label=\"Holodeck\\nescape pod (infinite loop)\\nmug\", color=red];\n"
+    ));
+    assert!(dot.contains("  \"Hull Breach\" [label=\"Hull Breach\", shape=doublecircle];\n"));
+    assert!(dot.contains("  \"Hull Breach\" -> \"Holodeck\" [label=\"north\"];\n"));
+    assert!(dot.contains(
+        "  \"Security Checkpoint\" [label=\"Security Checkpoint\", shape=box, style=bold];\n"
+    ));
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use bitvec::{boxed::BitBox, slice::BitSlice};
+
+use crate::{Graph, Path, Vertex};
+
+/// The command that follows a step, as sent to [`run_vm`](crate::run_vm).
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Path::Door(door) => write!(f, "{}", door),
+            Path::Take(item) => write!(f, "take {}", item),
+            Path::Drop(item) => write!(f, "drop {}", item),
+        }
+    }
+}
+
+/// For each room reachable from the root of a breadth-first search, the room it was
+/// entered from and the door taken.
+type Parents<'r> = HashMap<&'r str, Option<(&'r str, &'r str)>>;
+
+impl Graph {
+    /// The room where `item` lies at the start of the game.
+    pub fn item_room(&self, item: &str) -> Option<&str> {
+        let idx = self.items.iter().position(|name| name == item)?;
+        self.room_of()[idx]
+    }
+
+    /// The room of each item, by index.
+    fn room_of(&self) -> Vec<Option<&str>> {
+        let mut rooms = vec![None; self.items.len()];
+        for (room, items) in &self.nodes {
+            for &idx in items {
+                rooms[idx] = Some(room.as_str());
+            }
+        }
+        rooms
+    }
+
+    fn parents(&self, from: &str) -> Parents<'_> {
+        let mut parents = HashMap::new();
+        let Some((from, _)) = self.nodes.get_key_value(from) else {
+            return parents;
+        };
+        let mut queue = VecDeque::new();
+        parents.insert(from.as_str(), None);
+        queue.push_back(from.as_str());
+        while let Some(room) = queue.pop_front() {
+            let mut doors: Vec<_> = self.edges.get(room).into_iter().flatten().collect();
+            doors.sort();
+            for (door, next) in doors {
+                if !parents.contains_key(next.as_str()) {
+                    parents.insert(next.as_str(), Some((room, door.as_str())));
+                    queue.push_back(next.as_str());
+                }
+            }
+        }
+        parents
+    }
+
+    /// The doors from the root of `parents` to `to`.
+    fn doors<'r>(parents: &Parents<'r>, to: &str) -> Option<Vec<Path<'r>>> {
+        let mut path = Vec::new();
+        let mut room = to;
+        while let Some(&(prev, door)) = parents.get(room)?.as_ref() {
+            path.push(Path::Door(door));
+            room = prev;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Shortest path from the room `from` to the room `to`.
+    pub fn goto<'r>(&'r self, from: &str, to: &str) -> Option<Vec<Path<'r>>> {
+        Self::doors(&self.parents(from), to)
+    }
+
+    /// Shortest path from the start that picks up the items of `target` and ends in its room.
+    pub fn collect_items<'r>(&'r self, target: &Vertex<'r>) -> Option<Vec<Path<'r>>> {
+        self.tour(&self.start, &target.items, target.node)
+    }
+
+    /// Shortest path from the room `from` that picks up the `wanted` items, when already
+    /// holding `held`.
+    pub fn collect_route<'r>(
+        &'r self,
+        from: &str,
+        held: &BitSlice,
+        wanted: &BitSlice,
+    ) -> Option<Vec<Path<'r>>> {
+        let mut missing = BitBox::from_bitslice(wanted);
+        missing &= !BitBox::from_bitslice(held);
+        self.plan(from, &missing, None)
+    }
+
+    /// Shortest tour from the room `from` that picks up the `wanted` items and ends in the room
+    /// `to`. The rooms with items are visited in the order that takes the fewest doors, found
+    /// with the Held-Karp algorithm, so up to about 16 of them are practical.
+    pub fn tour<'r>(&'r self, from: &str, wanted: &BitSlice, to: &str) -> Option<Vec<Path<'r>>> {
+        self.plan(from, wanted, Some(to))
+    }
+
+    /// [`tour`](Self::tour), ending wherever is shortest when `to` is `None`.
+    fn plan<'r>(
+        &'r self,
+        from: &str,
+        wanted: &BitSlice,
+        to: Option<&str>,
+    ) -> Option<Vec<Path<'r>>> {
+        self.nodes.get(from)?;
+        let room_of = self.room_of();
+        let mut stops: Vec<&str> = Vec::new();
+        for idx in wanted.iter_ones() {
+            let room = room_of[idx]?;
+            if !stops.contains(&room) {
+                stops.push(room);
+            }
+        }
+        stops.sort();
+
+        // Doors between the stops, from the start to each stop, and from each stop to the end
+        let start = self.parents(from);
+        let parents: Vec<Parents> = stops.iter().map(|room| self.parents(room)).collect();
+        let dist = |parents: &Parents, to: &str| Some(Self::doors(parents, to)?.len());
+        let between: Vec<Vec<Option<usize>>> = parents
+            .iter()
+            .map(|parents| stops.iter().map(|room| dist(parents, room)).collect())
+            .collect();
+        let to_end: Vec<Option<usize>> = parents
+            .iter()
+            .map(|parents| to.map_or(Some(0), |to| dist(parents, to)))
+            .collect();
+
+        // Fewest doors to visit the stops in `mask`, ending with stop `last`, and the stop
+        // before it
+        let k = stops.len();
+        let mut best: Vec<Vec<Option<(usize, usize)>>> = vec![vec![None; k]; 1 << k];
+        for (i, room) in stops.iter().enumerate() {
+            best[1 << i][i] = dist(&start, room).map(|d| (d, usize::MAX));
+        }
+        for mask in 1..1usize << k {
+            for last in 0..k {
+                let Some((cost, _)) = best[mask][last] else {
+                    continue;
+                };
+                for next in (0..k).filter(|&next| mask & (1 << next) == 0) {
+                    let Some(d) = between[last][next] else {
+                        continue;
+                    };
+                    let entry = &mut best[mask | (1 << next)][next];
+                    if entry.is_none_or(|(c, _)| cost + d < c) {
+                        *entry = Some((cost + d, last));
+                    }
+                }
+            }
+        }
+
+        let full = (1 << k) - 1;
+        let mut order = Vec::new();
+        if k > 0 {
+            let (_, mut last) = (0..k)
+                .filter_map(|last| {
+                    let (cost, _) = best[full][last]?;
+                    Some((cost + to_end[last]?, last))
+                })
+                .min()?;
+            let mut mask = full;
+            while last != usize::MAX {
+                order.push(last);
+                let (_, prev) = best[mask][last].unwrap();
+                mask &= !(1 << last);
+                last = prev;
+            }
+            order.reverse();
+        }
+
+        let mut path = Vec::new();
+        let mut here = &start;
+        for i in order {
+            path.extend(Self::doors(here, stops[i])?);
+            for &idx in &self.nodes[stops[i]] {
+                if wanted[idx] {
+                    path.push(Path::Take(&self.items[idx]));
+                }
+            }
+            here = &parents[i];
+        }
+        if let Some(to) = to {
+            path.extend(Self::doors(here, to)?);
+        }
+        Some(path)
+    }
+}
+
+#[test]
+fn test_tour() {
+    use bitvec::{bitbox, order::Lsb0};
+
+    // A corridor from west to east, with a side room north of its middle
+    let mut graph = Graph::from_edges(&[
+        ("Arcade", "east", "Hallway"),
+        ("Hallway", "east", "Kitchen"),
+        ("Hallway", "north", "Library"),
+        ("Kitchen", "east", "Observatory"),
+    ]);
+    graph.items = vec!["mug".to_string(), "book".to_string(), "map".to_string()];
+    graph.hazards = vec![None; 3];
+    for (idx, room) in ["Kitchen", "Library", "Observatory"]
+        .into_iter()
+        .enumerate()
+    {
+        graph.nodes.get_mut(room).unwrap().push(idx);
+    }
+
+    assert_eq!(graph.item_room("book"), Some("Library"));
+    assert_eq!(graph.item_room("lamp"), None);
+
+    let commands = |path: Vec<Path>| path.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    let wanted = bitbox![1, 1, 0];
+    // Ending in the Arcade, the order doesn't matter: 6 doors either way
+    let tour = graph.tour("Arcade", &wanted, "Arcade").unwrap();
+    assert_eq!(tour.len(), 8);
+    // Ending in the Observatory, the Library comes first
+    let tour = graph.tour("Arcade", &wanted, "Observatory").unwrap();
+    assert_eq!(commands(tour), [
+        "east",
+        "north",
+        "take book",
+        "south",
+        "east",
+        "take mug",
+        "east"
+    ]);
+    // Nothing to pick up
+    let tour = graph.tour("Library", &bitbox![0; 3], "Kitchen").unwrap();
+    assert_eq!(commands(tour), ["south", "east"]);
+    assert!(graph.tour("Arcade", &wanted, "Nowhere").is_none());
+
+    // Without an end, the tour stops at the last item
+    let route = graph.collect_route("Kitchen", &bitbox![1, 0, 0], &bitbox![1, 0, 1]);
+    assert_eq!(commands(route.unwrap()), ["east", "take map"]);
+    assert_eq!(commands(graph.goto("Library", "Arcade").unwrap()), [
+        "south", "west"
+    ]);
+}
@@ -0,0 +1,160 @@
+//! Transcripts of the commands sent to the game and its responses.
+//!
+//! [`run_vm`](crate::run_vm) and [`run_vm_may_halt`](crate::run_vm_may_halt) record into the
+//! [`Recording`] of the calling thread, if any. Commands sent from other threads, or by
+//! stepping a VM directly, are not recorded.
+
+use std::{cell::RefCell, fs, io, marker::PhantomData, path::Path};
+
+use intcode::testing::{Mismatch, Transcript, check};
+
+thread_local! {
+    static RECORDER: RefCell<Option<Transcript>> = const { RefCell::new(None) };
+}
+
+/// Append `command` and what the game printed in response to `transcript`. The output comes
+/// as complete lines, except when the game halts in the middle of one.
+pub fn append(transcript: &mut Transcript, command: Option<&str>, output: &str, halted: bool) {
+    let mut t = std::mem::take(transcript);
+    if let Some(command) = command {
+        t = t.send(command);
+    }
+    for line in output.split_inclusive('\n') {
+        t = match line.strip_suffix('\n') {
+            Some(line) => t.expect(line),
+            None => t.output(&crate::convert_ascii(line.as_bytes())),
+        };
+    }
+    if halted {
+        t = t.halt();
+    }
+    *transcript = t;
+}
+
+/// Recording in progress on the thread that started it. It stops when dropped.
+#[must_use = "the recording stops when dropped"]
+pub struct Recording {
+    // Tied to the thread-local recorder
+    _thread: PhantomData<*const ()>,
+}
+
+impl Recording {
+    /// Stop recording, and return what was recorded.
+    pub fn finish(self) -> Transcript {
+        RECORDER.with(|r| r.borrow_mut().take()).unwrap_or_default()
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        RECORDER.with(|r| r.borrow_mut().take());
+    }
+}
+
+/// Record the commands sent by [`run_vm`](crate::run_vm) and
+/// [`run_vm_may_halt`](crate::run_vm_may_halt) on this thread, and their responses, until
+/// the recording is finished or dropped. Panics if this thread is already recording.
+pub fn start_recording() -> Recording {
+    RECORDER.with(|r| {
+        let mut r = r.borrow_mut();
+        assert!(r.is_none(), "Already recording on this thread");
+        *r = Some(Transcript::new());
+    });
+    Recording {
+        _thread: PhantomData,
+    }
+}
+
+/// Run `f` without recording, for commands sent to a copy of the game.
+pub fn unrecorded<T>(f: impl FnOnce() -> T) -> T {
+    // Put the recording back even if `f` panics
+    struct Paused(Option<Transcript>);
+
+    impl Drop for Paused {
+        fn drop(&mut self) {
+            RECORDER.with(|r| *r.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _paused = Paused(RECORDER.with(|r| r.borrow_mut().take()));
+    f()
+}
+
+pub(crate) fn record(command: &str, output: &str, halted: bool) {
+    RECORDER.with(|r| {
+        if let Some(transcript) = r.borrow_mut().as_mut() {
+            append(transcript, Some(command), output, halted);
+        }
+    });
+}
+
+pub fn write_transcript(path: impl AsRef<Path>, transcript: &Transcript) -> io::Result<()> {
+    fs::write(path, transcript.to_string())
+}
+
+pub fn read_transcript(path: impl AsRef<Path>) -> io::Result<Transcript> {
+    fs::read_to_string(path)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Feed the commands of `transcript` to a new game, and check that it responds the same.
+pub fn replay(program: &[isize], transcript: &Transcript) -> Result<(), Box<Mismatch>> {
+    check(program, transcript).map_err(Box::new)
+}
+
+#[test]
+fn test_record() {
+    use intcode::{VM, testing::fixtures::ECHO};
+
+    use crate::{run_vm, run_vm_may_halt};
+
+    let echo = ECHO;
+    let mut vm = VM::init(echo.to_vec());
+    run_vm(&mut vm, "not recorded").unwrap();
+    let recording = start_recording();
+    run_vm(&mut vm, "hi").unwrap();
+    unrecorded(|| run_vm(&mut vm.clone(), "ho").unwrap());
+    // Other threads don't record
+    let mut other = vm.clone();
+    std::thread::spawn(move || run_vm(&mut other, "ha").unwrap())
+        .join()
+        .unwrap();
+    run_vm_may_halt(&mut vm, "x").unwrap();
+    let transcript = recording.finish();
+    assert_eq!(
+        transcript.to_string(),
+        "<< hi\n>> hi\n> 63, 32\n<< x\n> 120\nhalt\n"
+    );
+
+    // A panic while unrecorded doesn't stop the recording
+    let recording = start_recording();
+    let panicked = std::panic::catch_unwind(|| unrecorded(|| panic!("lost"))).is_err();
+    assert!(panicked);
+    run_vm(&mut VM::init(echo.to_vec()), "hi").unwrap();
+    assert_eq!(recording.finish().exchanges.len(), 1);
+
+    // Dropping a recording stops it
+    drop(start_recording());
+    let recording = start_recording();
+    assert_eq!(recording.finish(), Transcript::new());
+
+    // A new game prints the prompt before the first command
+    let mut fresh = Transcript::new();
+    append(&mut fresh, Some("hi"), "? hi\n? ", false);
+    append(&mut fresh, Some("x"), "x", true);
+    assert!(replay(echo, &fresh).is_ok());
+    let mut wrong = Transcript::new();
+    append(&mut wrong, Some("hi"), "? ho\n? ", false);
+    assert_eq!(replay(echo, &wrong).unwrap_err().exchange, 0);
+}
+
+#[test]
+fn test_replay_solution() {
+    // Recorded with `main --record solution.transcript`
+    let program = intcode::parse_program(&fs::read_to_string("input.txt").unwrap());
+    let transcript = read_transcript("solution.transcript").unwrap();
+    if let Err(mismatch) = replay(&program, &transcript) {
+        panic!("{}", mismatch);
+    }
+}
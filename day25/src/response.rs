@@ -0,0 +1,277 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// The verdict of the robotic voice, whoever says it
+static VERDICT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(heavier|lighter) than the detected value|Analysis complete|You may proceed")
+        .unwrap()
+});
+/// The first number after the floor accepted the droid
+static PASSWORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b([0-9]+)\b").unwrap());
+
+/// A room, as described when entering it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Room {
+    pub title: String,
+    pub description: String,
+    pub doors: Vec<String>,
+    pub items: Vec<String>,
+}
+
+/// What the pressure-sensitive floor thinks of the droid's weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// "Droids on this ship are heavier than the detected value!"
+    TooLight,
+    /// "Droids on this ship are lighter than the detected value!"
+    TooHeavy,
+    /// "Analysis complete! You may proceed."
+    Accepted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Room(Room),
+    Took(String),
+    Dropped(String),
+    Inventory(Vec<String>),
+    /// Tried to take an item that isn't in the room
+    NoSuchItem,
+    /// Tried to drop an item that isn't carried
+    NotCarrying,
+    CantGo,
+    /// The droid can't move at all, e.g. because of the giant electromagnet
+    Stuck,
+    Unrecognized,
+    /// The robotic voice's verdict. The droid is ejected back to the checkpoint unless it was
+    /// accepted.
+    Checkpoint(Verdict),
+    /// The airlock password
+    Password(String),
+    /// The last words of the game, when it halts without a password
+    GameOver(String),
+    /// Any other text
+    Message(String),
+}
+
+/// Everything the game printed in reply to one command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Response {
+    pub events: Vec<Event>,
+    /// The game asked for the next command
+    pub prompt: bool,
+}
+
+fn list_item(line: &str) -> Option<String> {
+    line.strip_prefix("- ").map(|s| s.to_string())
+}
+
+fn parse_line(line: &str) -> Event {
+    if let Some(item) = line
+        .strip_prefix("You take the ")
+        .and_then(|s| s.strip_suffix('.'))
+    {
+        return Event::Took(item.to_string());
+    }
+    if let Some(item) = line
+        .strip_prefix("You drop the ")
+        .and_then(|s| s.strip_suffix('.'))
+    {
+        return Event::Dropped(item.to_string());
+    }
+    if let Some(captures) = VERDICT.captures(line) {
+        let verdict = match captures.get(1).map(|m| m.as_str()) {
+            Some("heavier") => Verdict::TooLight,
+            Some(_) => Verdict::TooHeavy,
+            None => Verdict::Accepted,
+        };
+        return Event::Checkpoint(verdict);
+    }
+    match line {
+        "You aren't carrying any items." => Event::Inventory(vec![]),
+        "You don't see that item here." => Event::NoSuchItem,
+        "You don't have that item." => Event::NotCarrying,
+        "You can't go that way." => Event::CantGo,
+        "Unrecognized command." => Event::Unrecognized,
+        _ if line.ends_with("You can't move!!") => Event::Stuck,
+        _ => Event::Message(line.to_string()),
+    }
+}
+
+impl Response {
+    pub fn parse(output: &str) -> Self {
+        let mut response = Response::default();
+        let mut lines = output.lines().map(str::trim_end).peekable();
+        while let Some(line) = lines.next() {
+            if line.is_empty() {
+                continue;
+            }
+            if line == "Command?" {
+                response.prompt = true;
+                continue;
+            }
+            response.prompt = false;
+
+            if let Some(title) = line.strip_prefix("== ").and_then(|s| s.strip_suffix(" ==")) {
+                let mut room = Room {
+                    title: title.to_string(),
+                    ..Room::default()
+                };
+                let mut description = Vec::new();
+                while let Some(&line) = lines.peek() {
+                    if line.is_empty() || line == "Command?" {
+                        break;
+                    }
+                    description.push(line);
+                    lines.next();
+                }
+                room.description = description.join("\n");
+                loop {
+                    while lines.next_if(|line| line.is_empty()).is_some() {}
+                    let list = match lines.peek() {
+                        Some(&"Doors here lead:") => &mut room.doors,
+                        Some(&"Items here:") => &mut room.items,
+                        _ => break,
+                    };
+                    lines.next();
+                    while let Some(item) = lines.peek().and_then(|line| list_item(line)) {
+                        list.push(item);
+                        lines.next();
+                    }
+                }
+                response.events.push(Event::Room(room));
+            } else if line == "Items in your inventory:" {
+                let mut items = Vec::new();
+                while let Some(item) = lines.peek().and_then(|line| list_item(line)) {
+                    items.push(item);
+                    lines.next();
+                }
+                response.events.push(Event::Inventory(items));
+            } else {
+                let accepted = response.verdict() == Some(Verdict::Accepted);
+                match PASSWORD.captures(line) {
+                    Some(captures) if accepted && response.password().is_none() => response
+                        .events
+                        .push(Event::Password(captures[1].to_string())),
+                    _ => response.events.push(parse_line(line)),
+                }
+            }
+        }
+
+        // Without a prompt or a password, the game is over
+        if !response.prompt && response.password().is_none() {
+            let last_words: Vec<String> = response
+                .events
+                .iter()
+                .rev()
+                .map_while(|event| match event {
+                    Event::Message(line) => Some(line.clone()),
+                    _ => None,
+                })
+                .collect();
+            if !last_words.is_empty() {
+                let n = response.events.len() - last_words.len();
+                response.events.truncate(n);
+                let last_words: Vec<String> = last_words.into_iter().rev().collect();
+                response.events.push(Event::GameOver(last_words.join("\n")));
+            }
+        }
+        response
+    }
+
+    pub fn rooms(&self) -> impl Iterator<Item = &Room> {
+        self.events.iter().filter_map(|event| match event {
+            Event::Room(room) => Some(room),
+            _ => None,
+        })
+    }
+
+    /// The room the droid ends up in.
+    pub fn room(&self) -> Option<&Room> {
+        self.rooms().last()
+    }
+
+    pub fn verdict(&self) -> Option<Verdict> {
+        self.events.iter().find_map(|event| match event {
+            Event::Checkpoint(verdict) => Some(*verdict),
+            _ => None,
+        })
+    }
+
+    /// The droid was sent back to the checkpoint by the pressure-sensitive floor.
+    pub fn ejected(&self) -> bool {
+        self.verdict()
+            .is_some_and(|verdict| verdict != Verdict::Accepted)
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.events.iter().find_map(|event| match event {
+            Event::Password(password) => Some(password.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn game_over(&self) -> Option<&str> {
+        self.events.iter().find_map(|event| match event {
+            Event::GameOver(words) => Some(words.as_str()),
+            _ => None,
+        })
+    }
+}
+
+#[test]
+fn test_parse_room() {
+    let output = "\n\n\n== Crew Quarters ==\nThe beds are all too small for you.\n\nDoors here lead:\n- east\n- south\n- west\n\nItems here:\n- astrolabe\n\nCommand?\n";
+    let response = Response::parse(output);
+    assert!(response.prompt);
+    assert_eq!(response.events, vec![Event::Room(Room {
+        title: "Crew Quarters".to_string(),
+        description: "The beds are all too small for you.".to_string(),
+        doors: vec!["east".to_string(), "south".to_string(), "west".to_string()],
+        items: vec!["astrolabe".to_string()],
+    })]);
+
+    let response = Response::parse("\nYou take the astrolabe.\n\nCommand?\n");
+    assert_eq!(response.events, vec![Event::Took("astrolabe".to_string())]);
+    let response =
+        Response::parse("\nItems in your inventory:\n- astrolabe\n- hologram\n\nCommand?\n");
+    assert_eq!(response.events, vec![Event::Inventory(vec![
+        "astrolabe".to_string(),
+        "hologram".to_string()
+    ])]);
+    let response = Response::parse("\nYou can't go that way.\n\nCommand?\n");
+    assert_eq!(response.events, vec![Event::CantGo]);
+}
+
+#[test]
+fn test_parse_checkpoint() {
+    let output = "\n\n\n== Pressure-Sensitive Floor ==\nAnalyzing...\n\nDoors here lead:\n- south\n\nA loud, robotic voice says \"Alert! Droids on this ship are heavier than the detected value!\" and you are ejected back to the checkpoint.\n\n\n\n== Security Checkpoint ==\nIn the next room, a pressure-sensitive floor will verify your identity.\n\nDoors here lead:\n- north\n- east\n\nCommand?\n";
+    let response = Response::parse(output);
+    assert_eq!(response.verdict(), Some(Verdict::TooLight));
+    assert!(response.ejected());
+    let titles: Vec<&str> = response.rooms().map(|room| room.title.as_str()).collect();
+    assert_eq!(titles, ["Pressure-Sensitive Floor", "Security Checkpoint"]);
+    assert_eq!(response.room().unwrap().doors, ["north", "east"]);
+
+    let output = "\n\n\n== Pressure-Sensitive Floor ==\nAnalyzing...\n\nDoors here lead:\n- south\n\nA loud, robotic voice says \"Analysis complete! You may proceed.\" and you enter the cockpit.\nSanta notices your small droid, looks puzzled for a moment, realizes what has happened, and radios your ship directly.\n\"Oh, hello! You should be able to get in by typing 2622472 on the keypad at the main airlock.\"\n";
+    let response = Response::parse(output);
+    assert_eq!(response.verdict(), Some(Verdict::Accepted));
+    assert_eq!(response.password(), Some("2622472"));
+    assert_eq!(response.game_over(), None);
+
+    // Only the numbers after the verdict count
+    let output = "\nStep 2 of 3\nA synthetic voice says \"You may proceed.\"\nThe airlock code is: 1234567, sent to your ship.\n";
+    let response = Response::parse(output);
+    assert_eq!(response.verdict(), Some(Verdict::Accepted));
+    assert_eq!(response.password(), Some("1234567"));
+
+    let response = Response::parse(
+        "\nYou take the molten lava.\n\nThe molten lava is way too hot! You melt!\n\n",
+    );
+    assert!(!response.prompt);
+    assert_eq!(
+        response.game_over(),
+        Some("The molten lava is way too hot! You melt!")
+    );
+}
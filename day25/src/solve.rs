@@ -0,0 +1,137 @@
+use std::{error, fmt, io};
+
+use bitvec::{bitbox, slice::BitSlice};
+use intcode::VM;
+
+use crate::{Graph, Path, Response, Vertex, check_weight, run_vm, solve_weight, unrecorded};
+
+/// The way into the cockpit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer {
+    pub password: String,
+    /// The items carried onto the pressure-sensitive floor
+    pub items: Vec<String>,
+    /// Number of sets of items tried on the floor
+    pub attempts: usize,
+}
+
+/// The step of [`solve`] that failed.
+#[derive(Debug)]
+pub enum SolveError {
+    /// No room leads to a pressure-sensitive floor
+    NoCheckpoint,
+    /// The safe items and the checkpoint can't all be reached from the start
+    Unreachable,
+    /// A command sent to the game failed
+    Game(io::Error),
+    /// The floor turned down every set of items, after this many attempts
+    NoSolution(usize),
+    /// The floor accepted the items, but no password followed
+    NoPassword(Response),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::NoCheckpoint => write!(f, "No checkpoint in the ship"),
+            SolveError::Unreachable => write!(f, "The items or the checkpoint can't be reached"),
+            SolveError::Game(e) => write!(f, "The game failed: {}", e),
+            SolveError::NoSolution(attempts) => {
+                write!(
+                    f,
+                    "No set of items gets through, after {} attempts",
+                    attempts
+                )
+            }
+            SolveError::NoPassword(response) => match response.game_over() {
+                Some(words) => write!(f, "No password, the game ended with {:?}", words),
+                None => write!(f, "No password after the floor accepted the items"),
+            },
+        }
+    }
+}
+
+impl error::Error for SolveError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SolveError::Game(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Pick up every safe item of the ship in `graph`, go to the checkpoint, and find which of
+/// the items get through the pressure-sensitive floor. The weights are tried on copies of
+/// the game, so only the winning attempt is recorded.
+pub fn solve(program: &[isize], graph: &Graph) -> Result<Answer, SolveError> {
+    let checkpoint = graph.checkpoint().ok_or(SolveError::NoCheckpoint)?;
+    let mut vm = VM::init(program.to_vec());
+
+    // Collect every item that can be picked up, and move right next to the floor
+    let target = Vertex {
+        node: checkpoint.room,
+        items: graph.safe_items(),
+    };
+    let path = graph
+        .collect_items(&target)
+        .ok_or(SolveError::Unreachable)?;
+    follow(&mut vm, &path).map_err(SolveError::Game)?;
+
+    let item_ids: Vec<usize> = target.items.iter_ones().collect();
+    let drop = |held: &BitSlice| {
+        let mut items = bitbox![0; graph.items.len()];
+        for i in held.iter_zeros() {
+            items.set(item_ids[i], true);
+        }
+        items
+    };
+    let solution = unrecorded(|| {
+        solve_weight(item_ids.len(), |held| {
+            let mut vm = vm.clone();
+            let (verdict, _response) = check_weight(&mut vm, graph, &drop(held))?;
+            Ok(verdict)
+        })
+    })
+    .map_err(SolveError::Game)?
+    .map_err(SolveError::NoSolution)?;
+
+    let (_verdict, response) =
+        check_weight(&mut vm, graph, &drop(&solution.items)).map_err(SolveError::Game)?;
+    let Some(password) = response.password() else {
+        return Err(SolveError::NoPassword(response));
+    };
+    Ok(Answer {
+        password: password.to_string(),
+        items: solution
+            .items
+            .iter_ones()
+            .map(|i| graph.items[item_ids[i]].clone())
+            .collect(),
+        attempts: solution.attempts,
+    })
+}
+
+fn follow(vm: &mut VM, path: &[Path]) -> io::Result<()> {
+    for step in path {
+        run_vm(vm, &step.to_string())?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_solve() {
+    let program = intcode::parse_program(&std::fs::read_to_string("input.txt").unwrap());
+    let mut graph = Graph::scan(program.clone()).unwrap();
+    let answer = solve(&program, &graph).unwrap();
+    assert_eq!(answer.password, "134349952");
+    assert!(answer.attempts > 0);
+
+    // Without the floor, there is no checkpoint
+    let checkpoint = graph.checkpoint().unwrap();
+    let (room, door) = (checkpoint.room.to_string(), checkpoint.door.to_string());
+    graph.edges.get_mut(&room).unwrap().remove(&door);
+    assert!(matches!(
+        solve(&program, &graph),
+        Err(SolveError::NoCheckpoint)
+    ));
+}
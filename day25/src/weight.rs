@@ -0,0 +1,105 @@
+use std::io;
+
+use bitvec::{boxed::BitBox, slice::BitSlice};
+
+use crate::Verdict;
+
+/// A set of items the pressure-sensitive floor accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub items: BitBox,
+    /// Number of sets tried on the floor, including the accepted one
+    pub attempts: usize,
+}
+
+/// Find the set of items the floor accepts, using its verdicts to skip sets that can't be
+/// right: a set too light rules out all its subsets, a set too heavy all its supersets.
+///
+/// `attempt` carries the given items onto the floor and returns the verdict. Sets are tried
+/// from half of the items outwards, so both kinds of verdict prune the search. Up to 64
+/// items are supported. When no set is accepted, the error is the number of sets tried.
+pub fn solve_weight(
+    n: usize,
+    mut attempt: impl FnMut(&BitSlice) -> io::Result<Verdict>,
+) -> io::Result<Result<Solution, usize>> {
+    assert!(n <= 64, "Too many items: {}", n);
+    let all = u64::MAX.checked_shr(64 - n as u32).unwrap_or(0);
+
+    // Largest sets known to be too light, and smallest sets known to be too heavy
+    let mut light: Vec<u64> = Vec::new();
+    let mut heavy: Vec<u64> = Vec::new();
+    let mut attempts = 0;
+
+    for count in order(n) {
+        let mut set = u64::MAX.checked_shr(64 - count).unwrap_or(0);
+        loop {
+            let too_light = light.iter().any(|&l| set & !l == 0);
+            let too_heavy = heavy.iter().any(|&h| h & !set == 0);
+            if !too_light && !too_heavy {
+                attempts += 1;
+                let items: BitBox = (0..n).map(|i| set & (1 << i) != 0).collect();
+                match attempt(&items)? {
+                    Verdict::Accepted => return Ok(Ok(Solution { items, attempts })),
+                    Verdict::TooLight => {
+                        light.retain(|&l| l & !set != 0);
+                        light.push(set);
+                    }
+                    Verdict::TooHeavy => {
+                        heavy.retain(|&h| set & !h != 0);
+                        heavy.push(set);
+                    }
+                }
+            }
+            match next_combination(set) {
+                Some(next) if next & !all == 0 => set = next,
+                _ => break,
+            }
+        }
+    }
+    Ok(Err(attempts))
+}
+
+/// Set sizes from `n / 2` outwards.
+fn order(n: usize) -> Vec<u32> {
+    let mut counts: Vec<u32> = (0..=n as u32).collect();
+    counts.sort_by_key(|&c| (c as isize - n as isize / 2).abs());
+    counts
+}
+
+/// The next larger set with as many items (Gosper's hack).
+fn next_combination(set: u64) -> Option<u64> {
+    if set == 0 {
+        return None;
+    }
+    let low = set & set.wrapping_neg();
+    let ripple = set.checked_add(low)?;
+    Some((((ripple ^ set) >> 2) / low) | ripple)
+}
+
+#[test]
+fn test_solve_weight() {
+    let weights = [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+    let target = 2 + 16 + 32 + 512 + 1024;
+    let mut tried = Vec::new();
+    let solution = solve_weight(weights.len(), |items| {
+        tried.push(BitBox::from_bitslice(items));
+        let weight: usize = items.iter_ones().map(|i| weights[i]).sum();
+        Ok(match weight.cmp(&target) {
+            std::cmp::Ordering::Less => Verdict::TooLight,
+            std::cmp::Ordering::Greater => Verdict::TooHeavy,
+            std::cmp::Ordering::Equal => Verdict::Accepted,
+        })
+    })
+    .unwrap()
+    .unwrap();
+    assert_eq!(solution.items.iter_ones().collect::<Vec<_>>(), vec![
+        1, 4, 5, 9, 10
+    ]);
+    assert_eq!(solution.attempts, tried.len());
+    // Trying the sets from the largest one down would take more than 2500 attempts
+    assert!(solution.attempts < 1000);
+
+    // Nothing is accepted: the 6 pairs, then the 4 triples that contain them, then all 4
+    let solution = solve_weight(4, |_| Ok(Verdict::TooLight)).unwrap();
+    assert_eq!(solution, Err(11));
+}
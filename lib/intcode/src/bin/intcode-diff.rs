@@ -0,0 +1,80 @@
+use std::{fs, io};
+
+use intcode::{
+    VM,
+    cli::{Args, invalid_data, invalid_input},
+    diff::VMDiff,
+    parse_program,
+};
+
+static USAGE: &str = "\
+Usage: intcode-diff [OPTIONS] <BEFORE> [AFTER]
+
+Compare two VM states. Each state is a snapshot file written by
+`intcode --snapshot`, or a program file which is loaded as a fresh VM.
+
+Options:
+  -s, --send <TEXT>      Run BEFORE until it waits for input, send TEXT and a
+                         newline as ASCII, run again, and compare the two states
+  -n, --values <VALUES>  Same as --send, with comma-separated integers
+  -h, --help             Print this help";
+
+fn load(path: &str) -> io::Result<VM> {
+    let content = fs::read_to_string(path)?;
+    if content.starts_with("pc ") {
+        VM::restore(&content).map_err(invalid_data)
+    } else {
+        Ok(VM::init(parse_program(&content)))
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = Args::new(USAGE);
+    let mut paths = Vec::new();
+    let mut send: Option<Vec<isize>> = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            "-s" | "--send" => {
+                let text = args.value(&arg)?;
+                let buf = send.get_or_insert_with(Vec::new);
+                buf.extend(text.bytes().map(|b| b as isize));
+                buf.push(b'\n' as isize);
+            }
+            "-n" | "--values" => {
+                let values = args.value(&arg)?;
+                let values = values
+                    .split(',')
+                    .map(|s| s.trim().parse::<isize>().map_err(invalid_input))
+                    .collect::<io::Result<Vec<_>>>()?;
+                send.get_or_insert_with(Vec::new).extend(values);
+            }
+            _ if arg.starts_with('-') => {
+                return Err(Args::unknown(&arg));
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let (before, after) = match (&paths[..], send) {
+        ([path], Some(input)) => {
+            let mut vm = load(path)?;
+            let _ = vm.run();
+            let before = vm.clone();
+            vm.write_port(&input);
+            let _ = vm.run();
+            (before, vm)
+        }
+        ([before, after], None) => (load(before)?, load(after)?),
+        _ => return Err(args.usage()),
+    };
+
+    let diff = VMDiff::new(&before, &after);
+    if diff.is_empty() {
+        println!("No difference");
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
@@ -0,0 +1,340 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use intcode::{
+    VM,
+    cli::{self, invalid_data, invalid_input},
+    coverage::Coverage,
+    parse_program,
+    patch::PatchFile,
+    script::Script,
+};
+
+static USAGE: &str = "\
+Usage: intcode [OPTIONS] <PROGRAM>
+
+Run an intcode program file.
+
+Options:
+  -i, --input <FILE>     Read input from FILE instead of stdin
+  -S, --script <FILE>    Run the input script in FILE instead of reading input,
+                         and fail on the first unmet expectation
+  -m, --mode <MODE>      Output mode: numeric, ascii or auto [default: numeric]
+  -I, --input-mode <MODE>
+                         Input mode: numeric, ascii or auto [default: auto]
+  -p, --patch <ADDR=VAL> Write VAL to ADDR before start (repeatable)
+  -f, --patch-file <FILE>
+                         Read named patch sets from FILE
+  -P, --apply <NAME>     Apply the patch set NAME from the patch file before
+                         the --patch edits (repeatable)
+  -s, --snapshot <FILE>  Write the VM state to FILE when the program halts or
+                         runs out of input
+  -r, --print-at <ADDR>  Print the value at ADDR when the program halts or runs
+                         out of input (repeatable)
+  -c, --coverage <FILE>  Write a coverage summary to FILE
+  -a, --accumulate       Add to the counters already in the coverage file
+  -l, --listing <FILE>   Write an annotated listing with coverage counters to FILE
+  -v, --verbose          Increase log verbosity (repeatable)
+  -q, --quiet            Disable logging
+  -h, --help             Print this help
+
+In numeric mode the input is a list of integers separated by commas or
+whitespace. In ascii mode the input is text, sent byte by byte. In auto
+mode, each line of integers is sent as numbers and any other line as text.
+Auto output mode prints values in the ASCII range as text and any other
+value as a number on its own line.";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Numeric,
+    Ascii,
+    Auto,
+}
+
+struct Args {
+    program: String,
+    input: Option<String>,
+    script: Option<String>,
+    mode: Mode,
+    input_mode: Mode,
+    patches: Vec<(usize, isize)>,
+    patch_file: Option<String>,
+    patch_sets: Vec<String>,
+    snapshot: Option<String>,
+    print_at: Vec<usize>,
+    coverage: Option<String>,
+    accumulate: bool,
+    listing: Option<String>,
+    level: log::LevelFilter,
+}
+
+fn parse_args() -> io::Result<Args> {
+    let mut args = cli::Args::new(USAGE);
+    let mut program = None;
+    let mut input = None;
+    let mut script = None;
+    let mut mode = Mode::Numeric;
+    let mut input_mode = Mode::Auto;
+    let mut patches = Vec::new();
+    let mut patch_file = None;
+    let mut patch_sets = Vec::new();
+    let mut snapshot = None;
+    let mut print_at = Vec::new();
+    let mut coverage = None;
+    let mut accumulate = false;
+    let mut listing = None;
+    let mut verbosity = 0;
+    let mut quiet = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => args.help(),
+            "-i" | "--input" => {
+                input = Some(args.value(&arg)?);
+            }
+            "-S" | "--script" => {
+                script = Some(args.value(&arg)?);
+            }
+            "-m" | "--mode" => {
+                mode = parse_mode(&args.value(&arg)?)?;
+            }
+            "-I" | "--input-mode" => {
+                input_mode = parse_mode(&args.value(&arg)?)?;
+            }
+            "-p" | "--patch" => {
+                patches.push(parse_patch(&args.value(&arg)?)?);
+            }
+            "-f" | "--patch-file" => {
+                patch_file = Some(args.value(&arg)?);
+            }
+            "-P" | "--apply" => {
+                patch_sets.push(args.value(&arg)?);
+            }
+            "-s" | "--snapshot" => {
+                snapshot = Some(args.value(&arg)?);
+            }
+            "-r" | "--print-at" => {
+                print_at.push(args.parse(&arg)?);
+            }
+            "-c" | "--coverage" => {
+                coverage = Some(args.value(&arg)?);
+            }
+            "-a" | "--accumulate" => {
+                accumulate = true;
+            }
+            "-l" | "--listing" => {
+                listing = Some(args.value(&arg)?);
+            }
+            "-v" | "--verbose" => {
+                verbosity += 1;
+            }
+            "-q" | "--quiet" => {
+                quiet = true;
+            }
+            _ if arg.starts_with('-') => {
+                return Err(cli::Args::unknown(&arg));
+            }
+            _ if program.is_none() => {
+                program = Some(arg);
+            }
+            _ => {
+                return Err(invalid_input(format!("Unexpected argument {:?}", arg)));
+            }
+        }
+    }
+
+    let level = match (quiet, verbosity) {
+        (true, _) => log::LevelFilter::Off,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Info,
+        (false, 2) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+
+    if input.is_some() && script.is_some() {
+        return Err(invalid_input("--input and --script are exclusive"));
+    }
+    if !patch_sets.is_empty() && patch_file.is_none() {
+        return Err(invalid_input("--apply needs a --patch-file"));
+    }
+
+    Ok(Args {
+        program: program.ok_or_else(|| args.usage())?,
+        input,
+        script,
+        mode,
+        input_mode,
+        patches,
+        patch_file,
+        patch_sets,
+        snapshot,
+        print_at,
+        coverage,
+        accumulate,
+        listing,
+        level,
+    })
+}
+
+fn parse_mode(s: &str) -> io::Result<Mode> {
+    match s {
+        "numeric" => Ok(Mode::Numeric),
+        "ascii" => Ok(Mode::Ascii),
+        "auto" => Ok(Mode::Auto),
+        m => Err(invalid_input(format!("Unknown mode {:?}", m))),
+    }
+}
+
+fn parse_patch(s: &str) -> io::Result<(usize, isize)> {
+    let (addr, val) = s
+        .split_once('=')
+        .ok_or_else(|| invalid_input(format!("Invalid patch {:?}, expected ADDR=VAL", s)))?;
+    let addr = addr.trim().parse().map_err(invalid_input)?;
+    let val = val.trim().parse().map_err(invalid_input)?;
+    Ok((addr, val))
+}
+
+fn parse_values(s: &str) -> io::Result<Vec<isize>> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(invalid_input))
+        .collect()
+}
+
+fn convert_input(mode: Mode, s: &str) -> io::Result<Vec<isize>> {
+    match mode {
+        Mode::Numeric => parse_values(s),
+        Mode::Ascii => Ok(s.bytes().map(|b| b as isize).collect()),
+        Mode::Auto => {
+            let mut values = Vec::new();
+            for line in s.split_inclusive('\n') {
+                match parse_values(line) {
+                    Ok(numbers) => values.extend(numbers),
+                    Err(_) => values.extend(line.bytes().map(|b| b as isize)),
+                }
+            }
+            Ok(values)
+        }
+    }
+}
+
+fn write_output(out: &mut impl Write, mode: Mode, data: &[isize]) -> io::Result<()> {
+    for &v in data {
+        match mode {
+            Mode::Numeric => writeln!(out, "{}", v)?,
+            Mode::Ascii => {
+                let b: u8 = v
+                    .try_into()
+                    .ok()
+                    .filter(u8::is_ascii)
+                    .ok_or_else(|| invalid_data(format!("Invalid byte {}", v)))?;
+                out.write_all(&[b])?;
+            }
+            Mode::Auto => {
+                if let Some(b) = v.try_into().ok().filter(u8::is_ascii) {
+                    out.write_all(&[b])?;
+                } else {
+                    writeln!(out, "\n{}", v)?;
+                }
+            }
+        }
+    }
+    out.flush()
+}
+
+fn main() -> io::Result<()> {
+    let args = parse_args()?;
+    env_logger::Builder::new().filter_level(args.level).init();
+
+    let mut program = parse_program(&fs::read_to_string(&args.program)?);
+    if let Some(path) = &args.patch_file {
+        let file = PatchFile::read(path)?;
+        for name in &args.patch_sets {
+            file.apply(name, &mut program)
+                .map_err(|e| invalid_data(format!("{}: {}", path, e)))?;
+        }
+    }
+    for &(addr, val) in &args.patches {
+        if program.len() <= addr {
+            program.resize(addr + 1, 0);
+        }
+        program[addr] = val;
+    }
+    let mut vm = VM::init(program.clone());
+    if args.coverage.is_some() || args.listing.is_some() {
+        vm.enable_coverage();
+    }
+
+    let mut stdout = io::stdout().lock();
+    if let Some(path) = &args.script {
+        let script: Script = fs::read_to_string(path)?.parse().map_err(invalid_data)?;
+        let output = script.run(&mut vm).map_err(invalid_data)?;
+        write_output(&mut stdout, args.mode, &output)?;
+    } else {
+        run_interactive(&args, &mut vm, &mut stdout)?;
+    }
+
+    if let Some(path) = &args.snapshot {
+        fs::write(path, vm.snapshot())?;
+    }
+    for &addr in &args.print_at {
+        writeln!(stdout, "{}", vm.read_at(addr))?;
+    }
+    if let Some(mut coverage) = vm.take_coverage() {
+        write_coverage(&args, &program, &mut coverage)?;
+    }
+    Ok(())
+}
+
+// A file is fed at once, stdin is fed line by line whenever the program waits for input
+fn run_interactive(args: &Args, vm: &mut VM, stdout: &mut impl Write) -> io::Result<()> {
+    let mut stdin = match &args.input {
+        Some(path) => {
+            vm.write_port(&convert_input(args.input_mode, &fs::read_to_string(path)?)?);
+            None
+        }
+        None => Some(io::stdin().lock()),
+    };
+
+    while vm.run().needs_input() {
+        write_output(stdout, args.mode, &vm.read_all())?;
+        let mut line = String::new();
+        if let Some(stdin) = &mut stdin {
+            stdin.read_line(&mut line)?;
+        }
+        if line.is_empty() {
+            if args.snapshot.is_some() || !args.print_at.is_empty() {
+                break;
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Program is waiting for more input",
+            ));
+        }
+        vm.write_port(&convert_input(args.input_mode, &line)?);
+    }
+    write_output(stdout, args.mode, &vm.read_all())?;
+    Ok(())
+}
+
+fn write_coverage(args: &Args, program: &[isize], coverage: &mut Coverage) -> io::Result<()> {
+    if let Some(path) = &args.coverage {
+        if args.accumulate {
+            match fs::read_to_string(path) {
+                Ok(prev) => {
+                    let prev: Coverage = prev.parse().map_err(invalid_data)?;
+                    coverage.merge(&prev);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+        fs::write(path, coverage.to_string())?;
+    }
+    if let Some(path) = &args.listing {
+        fs::write(path, coverage.listing(program))?;
+    }
+    Ok(())
+}
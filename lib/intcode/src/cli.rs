@@ -0,0 +1,110 @@
+//! Plumbing for the hand-written argument parsing of the binaries. Each binary matches its
+//! own options, and gets their values and its errors from here.
+
+use std::{
+    env, format, io, println, process,
+    string::{String, ToString},
+    vec::{self, Vec},
+};
+
+/// Error for a bad command line.
+pub fn invalid_input<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+/// Error for a file that can't be parsed.
+pub fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// The command-line arguments after the program name.
+pub struct Args {
+    args: vec::IntoIter<String>,
+    usage: &'static str,
+}
+
+impl Args {
+    pub fn new(usage: &'static str) -> Self {
+        Self::from_vec(usage, env::args().skip(1).collect())
+    }
+
+    pub fn from_vec(usage: &'static str, args: Vec<String>) -> Self {
+        Args {
+            args: args.into_iter(),
+            usage,
+        }
+    }
+
+    /// Print the usage, for `-h` and `--help`, and exit.
+    pub fn help(&self) -> ! {
+        println!("{}", self.usage);
+        process::exit(0);
+    }
+
+    /// The argument after the option `option`.
+    pub fn value(&mut self, option: &str) -> io::Result<String> {
+        self.args
+            .next()
+            .ok_or_else(|| invalid_input(format!("Missing value for {}", option)))
+    }
+
+    /// The argument after the option `option`, parsed.
+    pub fn parse<T>(&mut self, option: &str) -> io::Result<T>
+    where
+        T: core::str::FromStr,
+        T::Err: ToString,
+    {
+        let value = self.value(option)?;
+        value.parse().map_err(|e: T::Err| {
+            invalid_input(format!(
+                "Invalid value {:?} for {}: {}",
+                value,
+                option,
+                e.to_string()
+            ))
+        })
+    }
+
+    /// Error for an option that no arm matched.
+    pub fn unknown(arg: &str) -> io::Error {
+        invalid_input(format!("Unknown option {:?}", arg))
+    }
+
+    /// Error for missing or extra arguments, showing the usage.
+    pub fn usage(&self) -> io::Error {
+        invalid_input(self.usage)
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.args.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{string::ToString, vec, vec::Vec};
+
+    use super::Args;
+
+    #[test]
+    fn test_args() {
+        let argv = ["-n", "12", "-s", "x", "prog", "-s"];
+        let mut args = Args::from_vec("Usage: test", argv.iter().map(|s| s.to_string()).collect());
+        assert_eq!(args.next().as_deref(), Some("-n"));
+        assert_eq!(args.parse::<usize>("-n").unwrap(), 12);
+        assert_eq!(args.next().as_deref(), Some("-s"));
+        assert!(args.parse::<usize>("-s").is_err());
+        assert_eq!(args.collect::<Vec<_>>(), ["prog", "-s"]);
+
+        let mut args = Args::from_vec("Usage: test", vec!["-s".to_string()]);
+        args.next();
+        let err = args.value("-s").unwrap_err();
+        assert_eq!(err.to_string(), "Missing value for -s");
+        assert_eq!(args.usage().to_string(), "Usage: test");
+        assert_eq!(Args::unknown("-x").to_string(), "Unknown option \"-x\"");
+    }
+}
@@ -0,0 +1,193 @@
+//! Runtime support for programs translated to Rust by [`crate::transpile`].
+
+use alloc::vec::Vec;
+use core::task::Poll;
+
+use crate::{Mode, Status, VM, VMError};
+
+/// Why a compiled program returned control to the [`Machine`].
+pub enum Exit {
+    /// Waiting for input at the current pc.
+    Pending,
+    /// Halted at the current pc.
+    Halt,
+    /// The current pc is not the start of a compiled block.
+    Unknown,
+    /// The program wrote into its own code. The pc points to the next instruction.
+    Modified,
+}
+
+pub type Compiled = fn(&mut Machine) -> Exit;
+
+/// A VM that runs the compiled translation of its program for as long as the code is
+/// unmodified. It steps through unknown entry points with the interpreter, and switches to
+/// the interpreter for good once the program writes into its own code.
+#[derive(Clone)]
+pub struct Machine {
+    vm: VM,
+    code: &'static [(usize, usize)],
+    compiled: Compiled,
+    fallback: bool,
+}
+
+impl Machine {
+    /// `code` lists the `[start, end)` address ranges covered by `compiled`, in order.
+    pub fn new(program: Vec<isize>, code: &'static [(usize, usize)], compiled: Compiled) -> Self {
+        Machine {
+            vm: VM::init(program),
+            code,
+            compiled,
+            fallback: false,
+        }
+    }
+
+    pub fn into_vm(self) -> VM {
+        self.vm
+    }
+
+    pub fn is_fallback(&self) -> bool {
+        self.fallback
+    }
+
+    pub fn write_port(&mut self, buf: &[isize]) {
+        self.vm.write_port(buf);
+    }
+
+    pub fn read_port(&mut self) -> Option<isize> {
+        self.vm.read_port()
+    }
+
+    pub fn read_exact(&mut self, buf: &mut [isize]) -> Poll<()> {
+        self.vm.read_exact(buf)
+    }
+
+    pub fn read_all(&mut self) -> Vec<isize> {
+        self.vm.read_all()
+    }
+
+    pub fn run(&mut self) -> Status {
+        loop {
+            if self.fallback {
+                return self.vm.run();
+            }
+            match (self.compiled)(self) {
+                Exit::Pending => return Status::NeedsInput,
+                Exit::Halt => return Status::Halted,
+                Exit::Modified => {
+                    debug!("Code modified, falling back at {}", self.vm.pc);
+                    self.fallback = true;
+                }
+                Exit::Unknown => {
+                    let target = self
+                        .write_target()
+                        .map(|addr| (addr, self.vm.read_at(addr)));
+                    match self.vm.step() {
+                        Poll::Pending => return Status::NeedsInput,
+                        Poll::Ready(Err(VMError::Halt)) => return Status::Halted,
+                        Poll::Ready(Ok(())) => {
+                            if let Some((addr, old)) = target
+                                && self.is_code(addr)
+                                && self.vm.read_at(addr) != old
+                            {
+                                self.fallback = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn run_ready(mut self) -> Vec<isize> {
+        if self.run().is_halted() {
+            self.vm.read_all()
+        } else {
+            panic!("Program is pending");
+        }
+    }
+
+    // Address written by the instruction at pc, for interpreted steps which don't go
+    // through `store`
+    fn write_target(&self) -> Option<usize> {
+        let (mode1, _, mode3, op) = VM::decode(self.vm.read_at(self.vm.pc)).ok()?;
+        let (mode, offset) = match op {
+            1 | 2 | 7 | 8 => (mode3, 3),
+            3 => (mode1, 1),
+            _ => return None,
+        };
+        if let Mode::Immediate = mode {
+            return None;
+        }
+        Some(self.vm.get_ptr(mode, offset))
+    }
+
+    fn is_code(&self, addr: usize) -> bool {
+        let idx = self.code.partition_point(|&(_, end)| end <= addr);
+        self.code.get(idx).is_some_and(|&(start, _)| start <= addr)
+    }
+
+    // Accessors used by the generated code
+
+    #[inline]
+    pub fn pc(&self) -> usize {
+        self.vm.pc
+    }
+
+    #[inline]
+    pub fn set_pc(&mut self, pc: usize) {
+        self.vm.pc = pc;
+    }
+
+    #[inline]
+    pub fn jump(&mut self, addr: isize) {
+        self.vm.pc = addr.try_into().unwrap();
+    }
+
+    #[inline]
+    pub fn load(&self, addr: usize) -> isize {
+        self.vm.read_at(addr)
+    }
+
+    /// Read a position-mode pointer from `addr`.
+    #[inline]
+    pub fn ptr(&self, addr: usize) -> usize {
+        let ptr = self.vm.read_at(addr);
+        if let Ok(ptr) = ptr.try_into() {
+            ptr
+        } else {
+            panic!("Trying to access addr {} at {}", ptr, addr)
+        }
+    }
+
+    #[inline]
+    pub fn rel(&self, offset: isize) -> usize {
+        if let Some(ptr) = self.vm.relative_base.checked_add_signed(offset) {
+            ptr
+        } else {
+            panic!("Trying to access addr {}+{}", self.vm.relative_base, offset)
+        }
+    }
+
+    /// Returns `true` if the write changed the compiled code.
+    #[inline]
+    pub fn store(&mut self, addr: usize, val: isize) -> bool {
+        let modified = self.is_code(addr) && self.vm.read_at(addr) != val;
+        self.vm.write_at(addr, val);
+        modified
+    }
+
+    #[inline]
+    pub fn adjust_base(&mut self, offset: isize) {
+        self.vm.relative_base = self.rel(offset);
+    }
+
+    #[inline]
+    pub fn input(&mut self) -> Option<isize> {
+        self.vm.input.pop_front()
+    }
+
+    #[inline]
+    pub fn output(&mut self, val: isize) {
+        self.vm.output.push_back(val);
+    }
+}
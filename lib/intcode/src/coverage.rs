@@ -0,0 +1,205 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+use crate::disasm::Instruction;
+
+/// Per-address counters of how often a cell was executed as an instruction, read as data
+/// and written by the program.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    exec: Vec<usize>,
+    read: Vec<usize>,
+    write: Vec<usize>,
+}
+
+fn incr(counter: &mut Vec<usize>, addr: usize, n: usize) {
+    if n == 0 {
+        return;
+    }
+    if counter.len() <= addr {
+        counter.resize(addr + 1, 0);
+    }
+    counter[addr] += n;
+}
+
+fn count(counter: &[usize], addr: usize) -> usize {
+    counter.get(addr).copied().unwrap_or(0)
+}
+
+fn add(counter: &mut Vec<usize>, other: &[usize]) {
+    if counter.len() < other.len() {
+        counter.resize(other.len(), 0);
+    }
+    for (c, &n) in counter.iter_mut().zip(other) {
+        *c += n;
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record_exec(&mut self, addr: usize) {
+        incr(&mut self.exec, addr, 1);
+    }
+
+    pub(crate) fn record_read(&mut self, addr: usize) {
+        incr(&mut self.read, addr, 1);
+    }
+
+    pub(crate) fn record_write(&mut self, addr: usize) {
+        incr(&mut self.write, addr, 1);
+    }
+
+    pub fn exec_count(&self, addr: usize) -> usize {
+        count(&self.exec, addr)
+    }
+
+    pub fn read_count(&self, addr: usize) -> usize {
+        count(&self.read, addr)
+    }
+
+    pub fn write_count(&self, addr: usize) -> usize {
+        count(&self.write, addr)
+    }
+
+    /// Accumulate the counters of another run into this one.
+    pub fn merge(&mut self, other: &Coverage) {
+        add(&mut self.exec, &other.exec);
+        add(&mut self.read, &other.read);
+        add(&mut self.write, &other.write);
+    }
+
+    fn len(&self) -> usize {
+        self.exec.len().max(self.read.len()).max(self.write.len())
+    }
+
+    /// Annotated listing of `mem`. Cells executed at least once are disassembled, other
+    /// cells are shown as data. Each line is prefixed with the exec/read/write counters.
+    pub fn listing(&self, mem: &[isize]) -> String {
+        let mut out = String::new();
+        out.push_str("   exec   read  write   addr\n");
+        let mut addr = 0;
+        while addr < mem.len() {
+            let inst = if self.exec_count(addr) > 0 {
+                Instruction::decode(mem, addr)
+            } else {
+                None
+            };
+            let (text, size) = match inst {
+                Some(inst) => (inst.to_string(), inst.size()),
+                None => (format!("data {}", mem[addr]), 1),
+            };
+            out.push_str(&format!(
+                "{:7}{:7}{:7} {:6}: {}\n",
+                self.exec_count(addr),
+                self.read_count(addr),
+                self.write_count(addr),
+                addr,
+                text
+            ));
+            addr += size;
+        }
+        out
+    }
+}
+
+/// The machine-readable summary has one line per touched address: `addr exec read write`.
+/// Lines starting with `#` are comments.
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# addr exec read write")?;
+        for addr in 0..self.len() {
+            let counts = (
+                self.exec_count(addr),
+                self.read_count(addr),
+                self.write_count(addr),
+            );
+            if counts != (0, 0, 0) {
+                writeln!(f, "{} {} {} {}", addr, counts.0, counts.1, counts.2)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Coverage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut coverage = Coverage::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line
+                .split_whitespace()
+                .map(|s| s.parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Invalid line {:?}: {}", line, e))?;
+            let &[addr, exec, read, write] = &fields[..] else {
+                return Err(format!("Invalid line {:?}", line));
+            };
+            incr(&mut coverage.exec, addr, exec);
+            incr(&mut coverage.read, addr, read);
+            incr(&mut coverage.write, addr, write);
+        }
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::VM;
+    use alloc::{string::ToString, vec};
+
+    use super::Coverage;
+
+    #[test]
+    fn test_coverage() {
+        // Output 1 if the input equals 8, else 0
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let mut vm = VM::init(program.clone());
+        vm.enable_coverage();
+        vm.write_port(&[8]);
+        assert!(vm.run().is_halted());
+        let coverage = vm.coverage().unwrap();
+        for addr in [0, 2, 6, 8] {
+            assert_eq!(coverage.exec_count(addr), 1, "addr {}", addr);
+        }
+        assert_eq!(coverage.exec_count(1), 0);
+        assert_eq!(coverage.read_count(9), 2);
+        assert_eq!(coverage.read_count(10), 1);
+        assert_eq!(coverage.write_count(9), 2);
+
+        let listing = coverage.listing(&program);
+        assert!(listing.contains("      1      0      0      2: eq [9], [10], [9]\n"));
+        assert!(listing.contains("      0      2      2      9: data -1\n"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let program = vec![104, 1, 99];
+        let mut vm = VM::init(program);
+        vm.enable_coverage();
+        assert!(vm.run().is_halted());
+        let mut total = vm.coverage().unwrap().clone();
+        assert_eq!(
+            total.to_string(),
+            "# addr exec read write\n0 1 0 0\n2 1 0 0\n"
+        );
+
+        let parsed: Coverage = total.to_string().parse().unwrap();
+        assert_eq!(&parsed, vm.coverage().unwrap());
+
+        total.merge(&parsed);
+        assert_eq!(total.exec_count(0), 2);
+        assert_eq!(total.exec_count(1), 0);
+    }
+}
@@ -0,0 +1,245 @@
+//! Detection of programs that loop forever without any I/O.
+//!
+//! Between two I/O instructions, the machine is deterministic: if its whole state repeats, it
+//! will repeat forever. The state is the pc, the relative base and a fingerprint of the
+//! memory, which is updated on each write instead of hashing the memory at every step. The
+//! input and output queues cannot change without I/O, so they are left out.
+//!
+//! Repeated states are found with Brent's algorithm, so a loop is detected after at most a
+//! few times its length. The pc reported is the one where the loop is entered: the memory is
+//! rewound to its state after the last I/O, with a log of the writes since then, and run
+//! again until it reaches a state of the loop.
+
+use alloc::vec::Vec;
+
+use crate::{VM, memory::Memory};
+
+/// Writes logged before the start of the search for the loop entry moves to the current
+/// state, to bound the memory of the log.
+const UNDO_LIMIT: usize = 1 << 16;
+
+/// Contribution of one cell to the fingerprint. Zero for cells holding zero, so growing the
+/// memory does not change the fingerprint.
+fn mix(addr: usize, val: isize) -> u64 {
+    if val == 0 {
+        return 0;
+    }
+    // splitmix64 finalizer
+    let mut x = (addr as u64)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        .wrapping_add(val as u64);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct State {
+    pc: usize,
+    relative_base: usize,
+    fingerprint: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct CycleDetector {
+    fingerprint: u64,
+    /// State the following ones are compared to
+    saved: Option<State>,
+    /// Steps since `saved`
+    steps: usize,
+    /// Steps before `saved` is replaced
+    power: usize,
+    /// State before the first step after the last I/O, where the loop entry is searched
+    /// from
+    start: State,
+    /// Address and old value of the cells written since `start`
+    undo: Vec<(usize, isize)>,
+    /// The last step read or wrote a mapped device
+    device_io: bool,
+    /// Writes are not logged while the memory is rewound
+    rewinding: bool,
+}
+
+impl CycleDetector {
+    /// Start watching a machine with memory `cells`, before it runs from `pc`.
+    pub(crate) fn new(cells: &[isize], pc: usize, relative_base: usize) -> Self {
+        let fingerprint = cells
+            .iter()
+            .enumerate()
+            .fold(0u64, |acc, (addr, &val)| acc.wrapping_add(mix(addr, val)));
+        CycleDetector {
+            fingerprint,
+            saved: None,
+            steps: 0,
+            power: 1,
+            start: State {
+                pc,
+                relative_base,
+                fingerprint,
+            },
+            undo: Vec::new(),
+            device_io: false,
+            rewinding: false,
+        }
+    }
+
+    fn state(&self, pc: usize, relative_base: usize) -> State {
+        State {
+            pc,
+            relative_base,
+            fingerprint: self.fingerprint,
+        }
+    }
+
+    pub(crate) fn record_write(&mut self, addr: usize, old: isize, new: isize) {
+        self.fingerprint = self
+            .fingerprint
+            .wrapping_sub(mix(addr, old))
+            .wrapping_add(mix(addr, new));
+        if !self.rewinding {
+            self.undo.push((addr, old));
+        }
+    }
+
+    pub(crate) fn record_device_io(&mut self) {
+        self.device_io = true;
+    }
+
+    /// Whether the last step accessed a device, which counts as I/O.
+    pub(crate) fn take_device_io(&mut self) -> bool {
+        core::mem::take(&mut self.device_io)
+    }
+
+    /// Forget the states seen so far, after I/O. The machine runs next from `pc`.
+    pub(crate) fn reset(&mut self, pc: usize, relative_base: usize) {
+        self.saved = None;
+        self.steps = 0;
+        self.power = 1;
+        self.start = self.state(pc, relative_base);
+        self.undo.clear();
+    }
+
+    /// Check the state before the next step. Returns the length of the loop when the state
+    /// repeats.
+    pub(crate) fn check(&mut self, pc: usize, relative_base: usize) -> Option<usize> {
+        let state = self.state(pc, relative_base);
+        if self.undo.len() > UNDO_LIMIT {
+            self.start = state;
+            self.undo.clear();
+        }
+        if self.saved == Some(state) {
+            return Some(self.steps);
+        }
+        if self.saved.is_none() || self.steps == self.power {
+            self.saved = Some(state);
+            self.power *= 2;
+            self.steps = 0;
+        }
+        self.steps += 1;
+        None
+    }
+}
+
+impl<M: Memory> VM<M> {
+    fn loop_state(&self) -> State {
+        self.cycles
+            .as_ref()
+            .unwrap()
+            .state(self.pc, self.relative_base)
+    }
+
+    /// Find the pc where the loop of `length` steps the machine is on is entered, since the
+    /// last I/O, and leave the machine there. Its future is the same from any state of the
+    /// loop.
+    pub(crate) fn loop_entry(&mut self, length: usize) -> usize {
+        let coverage = self.coverage.take();
+        self.cycles.as_mut().unwrap().rewinding = true;
+
+        // Go around the loop once
+        let mut on_loop = Vec::with_capacity(length);
+        for _ in 0..length {
+            on_loop.push(self.loop_state());
+            let _ = self.step();
+        }
+        on_loop.sort_unstable();
+
+        // Back to the start, and forward to the first state of the loop
+        let cycles = self.cycles.as_mut().unwrap();
+        let undo = core::mem::take(&mut cycles.undo);
+        let start = cycles.start;
+        for &(addr, old) in undo.iter().rev() {
+            self.write_at(addr, old);
+        }
+        self.pc = start.pc;
+        self.relative_base = start.relative_base;
+        while on_loop.binary_search(&self.loop_state()).is_err() {
+            let _ = self.step();
+        }
+
+        let cycles = self.cycles.as_mut().unwrap();
+        cycles.rewinding = false;
+        cycles.reset(self.pc, self.relative_base);
+        self.coverage = coverage;
+        self.pc
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use crate::{Status, VM, device::Rng};
+
+    #[test]
+    fn test_infinite_loop() {
+        // Output the input, then count down from it and loop forever at 0
+        let program = vec![
+            3, 20, 4, 20, 1006, 20, 11, 1001, 20, -1, 20, 1105, 1, 4, 99, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut vm = VM::init(program);
+        vm.enable_loop_detection();
+        vm.write_port(&[1000]);
+        assert_eq!(vm.run(), Status::InfiniteLoop(11));
+        assert_eq!(vm.read_all(), vec![1000]);
+        assert_eq!((vm.pc, vm.read_at(20)), (11, 0));
+
+        // A mapped device is only I/O when the loop uses it
+        let mut vm = VM::init(vec![1105, 1, 0]);
+        vm.map_device(100..101, Rng::new(1));
+        vm.enable_loop_detection();
+        assert_eq!(vm.run(), Status::InfiniteLoop(0));
+        let mut vm = VM::init(vec![1006, 100, 0, 1105, 1, 0]);
+        vm.map_device(100..101, Rng::new(1));
+        vm.enable_loop_detection();
+        assert_eq!(vm.run_limit(100_000), Status::LimitReached);
+
+        // A counter changes the memory, so it never repeats
+        let mut vm = VM::init(vec![1001, 7, 1, 7, 1105, 1, 0, 0]);
+        vm.enable_loop_detection();
+        assert_eq!(vm.run_limit(100_000), Status::LimitReached);
+
+        // Spin on a jump
+        let mut vm = VM::init(vec![104, 7, 1105, 1, 2]);
+        vm.enable_loop_detection();
+        assert_eq!(vm.run_until_output(), Status::Output);
+        assert_eq!(vm.run(), Status::InfiniteLoop(2));
+
+        // A loop entered at the very first instruction
+        let mut vm = VM::init(vec![1105, 1, 3, 1105, 1, 0]);
+        vm.enable_loop_detection();
+        assert_eq!(vm.run(), Status::InfiniteLoop(0));
+
+        // A loop entered right after an output, by a jump past its first instruction
+        let mut vm = VM::init(vec![104, 7, 1105, 1, 5, 1105, 1, 2]);
+        vm.enable_loop_detection();
+        assert_eq!(vm.run(), Status::InfiniteLoop(2));
+        assert_eq!(vm.read_all(), vec![7]);
+        assert_eq!(vm.pc, 2);
+
+        // And right after an input
+        let mut vm = VM::init(vec![3, 9, 1105, 1, 5, 1105, 1, 2, 99, 0]);
+        vm.enable_loop_detection();
+        vm.write_port(&[1]);
+        assert_eq!(vm.run(), Status::InfiniteLoop(2));
+    }
+}
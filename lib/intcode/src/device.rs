@@ -0,0 +1,283 @@
+//! Memory-mapped devices. Reads and writes of a mapped address range go to the device
+//! instead of memory.
+//!
+//! Only data accesses of instructions are mapped. [`VM::read_at`] and [`VM::write_at`]
+//! always see plain memory, and so do snapshots and diffs.
+//!
+//! The VM owns its devices, like its memory: a clone of the VM gets a copy of each device.
+//! Devices only need `alloc`, except for the [`Clock`] which needs `std`.
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::{any::Any, ops::Range};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::{VM, memory::Memory};
+
+pub trait Device: Any + Send + DeviceClone {
+    /// Read the cell at `offset` from the start of the mapped range.
+    fn read(&mut self, offset: usize) -> isize;
+
+    /// Write the cell at `offset` from the start of the mapped range.
+    fn write(&mut self, offset: usize, val: isize);
+}
+
+/// Copy of a device for a clone of the VM, implemented for every `Clone` device.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<D: Device + Clone> DeviceClone for D {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+pub(crate) struct Mapping {
+    range: Range<usize>,
+    device: Box<dyn Device>,
+}
+
+impl Clone for Mapping {
+    fn clone(&self) -> Self {
+        Mapping {
+            range: self.range.clone(),
+            device: self.device.clone_box(),
+        }
+    }
+}
+
+impl<M: Memory> VM<M> {
+    /// Map `range` to `device`. It can be inspected later with [`VM::device`].
+    pub fn map_device<D: Device>(&mut self, range: Range<usize>, device: D) {
+        if let Some(m) = self
+            .devices
+            .iter()
+            .find(|m| m.range.start < range.end && range.start < m.range.end)
+        {
+            panic!("Device range {:?} overlaps {:?}", range, m.range);
+        }
+        self.devices.push(Mapping {
+            range,
+            device: Box::new(device),
+        });
+    }
+
+    /// The device mapped at `addr`, if it is a `D`.
+    pub fn device<D: Device>(&self, addr: usize) -> Option<&D> {
+        let (m, _) = self.device_at(addr)?;
+        (m.device.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    pub fn device_mut<D: Device>(&mut self, addr: usize) -> Option<&mut D> {
+        let m = self.devices.iter_mut().find(|m| m.range.contains(&addr))?;
+        (m.device.as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    /// Remove the device mapped at `addr`. Returns `false` if there was none.
+    pub fn unmap_device(&mut self, addr: usize) -> bool {
+        let len = self.devices.len();
+        self.devices.retain(|m| !m.range.contains(&addr));
+        self.devices.len() < len
+    }
+
+    fn device_at(&self, addr: usize) -> Option<(&Mapping, usize)> {
+        self.devices
+            .iter()
+            .find(|m| m.range.contains(&addr))
+            .map(|m| (m, addr - m.range.start))
+    }
+
+    fn device_at_mut(&mut self, addr: usize) -> Option<(&mut Mapping, usize)> {
+        self.devices
+            .iter_mut()
+            .find(|m| m.range.contains(&addr))
+            .map(|m| {
+                let offset = addr - m.range.start;
+                (m, offset)
+            })
+    }
+
+    pub(crate) fn device_read(&mut self, addr: usize) -> Option<isize> {
+        let (m, offset) = self.device_at_mut(addr)?;
+        let val = m.device.read(offset);
+        if let Some(cycles) = &mut self.cycles {
+            cycles.record_device_io();
+        }
+        Some(val)
+    }
+
+    /// Returns `false` if `addr` is not mapped.
+    pub(crate) fn device_write(&mut self, addr: usize, val: isize) -> bool {
+        if let Some((m, offset)) = self.device_at_mut(addr) {
+            m.device.write(offset, val);
+            if let Some(cycles) = &mut self.cycles {
+                cycles.record_device_io();
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A `width` x `height` grid of cells, in row-major order.
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<isize>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Size of the range to map.
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> isize {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Draw each cell as `palette[value]`, or `?` for values outside of the palette.
+    pub fn render(&self, palette: &[char]) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            for &v in row {
+                let c = usize::try_from(v).ok().and_then(|v| palette.get(v));
+                out.push(*c.unwrap_or(&'?'));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Device for Framebuffer {
+    fn read(&mut self, offset: usize) -> isize {
+        self.pixels[offset]
+    }
+
+    fn write(&mut self, offset: usize, val: isize) {
+        self.pixels[offset] = val;
+    }
+}
+
+/// Random-number port. Each read returns a new non-negative number, and a write reseeds
+/// the generator.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed.max(1) }
+    }
+}
+
+impl Device for Rng {
+    // xorshift64
+    fn read(&mut self, _offset: usize) -> isize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 1) as isize
+    }
+
+    fn write(&mut self, _offset: usize, val: isize) {
+        self.state = (val as u64).max(1);
+    }
+}
+
+/// Milliseconds since the clock was created or last written to.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct Clock {
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Device for Clock {
+    fn read(&mut self, _offset: usize) -> isize {
+        self.start.elapsed().as_millis() as isize
+    }
+
+    fn write(&mut self, _offset: usize, _val: isize) {
+        self.start = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Framebuffer, Rng};
+    use crate::VM;
+    use alloc::vec;
+
+    #[test]
+    fn test_framebuffer() {
+        // Draw a diagonal on a 3x3 framebuffer at 1000, with color 1 + input
+        let mut vm = VM::init(vec![
+            3, 100, 1001, 100, 1, 100, 1001, 100, 0, 1000, 1001, 100, 0, 1004, 1001, 100, 0, 1008,
+            99,
+        ]);
+        vm.map_device(1000..1009, Framebuffer::new(3, 3));
+        vm.write_port(&[1]);
+        let mut clone = vm.clone();
+        assert!(vm.run().is_halted());
+        let fb = vm.device::<Framebuffer>(1004).unwrap();
+        assert_eq!(fb.render(&[' ', '#', '@']), "@  \n @ \n  @\n");
+        assert_eq!(vm.read_at(1000), 0);
+        assert!(vm.device::<Rng>(1004).is_none());
+
+        // The clone draws on its own copy
+        vm.device_mut::<Framebuffer>(1000).unwrap().pixels.fill(0);
+        assert!(clone.run().is_halted());
+        assert_eq!(clone.device::<Framebuffer>(1000).unwrap().get(2, 2), 2);
+    }
+
+    #[test]
+    fn test_rng() {
+        // Output two reads of the port at 500, then reseed it and read again
+        let program = vec![4, 500, 4, 500, 1101, 0, 7, 500, 4, 500, 99];
+        let mut vm = VM::init(program.clone());
+        vm.map_device(500..501, Rng::new(7));
+        let out = vm.run_ready();
+        assert_ne!(out[0], out[1]);
+        assert_eq!(out[0], out[2]);
+        assert!(out.iter().all(|&v| v >= 0));
+
+        let mut vm = VM::init(program);
+        vm.map_device(500..501, Rng::new(7));
+        assert!(vm.unmap_device(500));
+        assert_eq!(vm.run_ready(), vec![0, 0, 7]);
+    }
+}
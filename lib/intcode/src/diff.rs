@@ -0,0 +1,140 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{
+    VM,
+    disasm::{Instruction, sweep},
+};
+
+/// Number of referencing instructions shown for each changed cell.
+const XREF_LIMIT: usize = 4;
+
+/// Differences between two machine states.
+pub struct VMDiff<'v> {
+    pub pc: Option<(usize, usize)>,
+    pub relative_base: Option<(usize, usize)>,
+    /// Changed memory cells as `(addr, before, after)`.
+    pub mem: Vec<(usize, isize, isize)>,
+    pub input: Option<(Vec<isize>, Vec<isize>)>,
+    pub output: Option<(Vec<isize>, Vec<isize>)>,
+    after: &'v VM,
+}
+
+fn changed<T: PartialEq>(before: T, after: T) -> Option<(T, T)> {
+    if before == after {
+        None
+    } else {
+        Some((before, after))
+    }
+}
+
+impl<'v> VMDiff<'v> {
+    pub fn new(before: &VM, after: &'v VM) -> Self {
+        let len = before.mem.len().max(after.mem.len());
+        let mem = (0..len)
+            .map(|addr| (addr, before.read_at(addr), after.read_at(addr)))
+            .filter(|&(_, x, y)| x != y)
+            .collect();
+        VMDiff {
+            pc: changed(before.pc, after.pc),
+            relative_base: changed(before.relative_base, after.relative_base),
+            mem,
+            input: changed(
+                before.input.iter().copied().collect(),
+                after.input.iter().copied().collect(),
+            ),
+            output: changed(
+                before.output.iter().copied().collect(),
+                after.output.iter().copied().collect(),
+            ),
+            after,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pc.is_none()
+            && self.relative_base.is_none()
+            && self.mem.is_empty()
+            && self.input.is_none()
+            && self.output.is_none()
+    }
+
+    /// Instructions of the later state that access `addr` in position mode.
+    pub fn xrefs(&self, addr: usize) -> Vec<Instruction<'v>> {
+        sweep(&self.after.mem)
+            .into_iter()
+            .filter(|inst| inst.position_operands().any(|p| p == addr))
+            .collect()
+    }
+}
+
+impl fmt::Display for VMDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((x, y)) = self.pc {
+            writeln!(f, "pc: {} -> {}", x, y)?;
+            if let Some(inst) = Instruction::decode(&self.after.mem, y) {
+                writeln!(f, "    {:6}: {}", y, inst)?;
+            }
+        }
+        if let Some((x, y)) = self.relative_base {
+            writeln!(f, "relative_base: {} -> {}", x, y)?;
+        }
+        if let Some((x, y)) = &self.input {
+            writeln!(f, "input: {:?} -> {:?}", x, y)?;
+        }
+        if let Some((x, y)) = &self.output {
+            writeln!(f, "output: {:?} -> {:?}", x, y)?;
+        }
+
+        let insts = sweep(&self.after.mem);
+        for &(addr, x, y) in &self.mem {
+            writeln!(f, "[{}]: {} -> {}", addr, x, y)?;
+            let xrefs = insts
+                .iter()
+                .filter(|inst| inst.position_operands().any(|p| p == addr));
+            for inst in xrefs.take(XREF_LIMIT) {
+                writeln!(f, "    {:6}: {}", inst.addr, inst)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::VM;
+    use alloc::{string::ToString, vec, vec::Vec};
+
+    use super::VMDiff;
+
+    #[test]
+    fn test_diff() {
+        // Add the input to a counter at 11 and print it, forever
+        let program = vec![3, 12, 1, 11, 12, 11, 4, 11, 1105, 1, 0, 100, 0];
+        let mut vm = VM::init(program);
+        assert!(vm.run().needs_input());
+        let before = vm.clone();
+        vm.write_port(&[5]);
+        assert!(vm.run().needs_input());
+
+        let diff = VMDiff::new(&before, &vm);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.pc, None);
+        assert_eq!(diff.relative_base, None);
+        assert_eq!(diff.mem, vec![(11, 100, 105), (12, 0, 5)]);
+        assert_eq!(diff.output, Some((vec![], vec![105])));
+        assert_eq!(diff.xrefs(11).len(), 2);
+        let expected = [
+            "output: [] -> [105]",
+            "[11]: 100 -> 105",
+            "         2: add [11], [12], [11]",
+            "         6: out [11]",
+            "[12]: 0 -> 5",
+            "         0: in [12]",
+            "         2: add [11], [12], [11]",
+        ];
+        assert_eq!(diff.to_string().lines().collect::<Vec<_>>(), expected);
+
+        assert!(VMDiff::new(&vm, &vm).is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::{Mode, VM};
+
+/// A decoded instruction, borrowed from program memory.
+pub struct Instruction<'m> {
+    pub addr: usize,
+    pub opcode: usize,
+    pub(crate) modes: [Mode; 3],
+    pub(crate) params: &'m [isize],
+}
+
+impl<'m> Instruction<'m> {
+    /// Decode the instruction at `addr`. Returns `None` if the cell is not a valid instruction,
+    /// or if the parameters run past the end of `mem`.
+    pub fn decode(mem: &'m [isize], addr: usize) -> Option<Self> {
+        let (mode1, mode2, mode3, opcode) = VM::decode(*mem.get(addr)?).ok()?;
+        let len = Self::size_of(opcode)?;
+        let params = mem.get(addr + 1..addr + len)?;
+        Some(Instruction {
+            addr,
+            opcode,
+            modes: [mode1, mode2, mode3],
+            params,
+        })
+    }
+
+    fn size_of(opcode: usize) -> Option<usize> {
+        match opcode {
+            1 | 2 | 7 | 8 => Some(4),
+            5 | 6 => Some(3),
+            3 | 4 | 9 => Some(2),
+            99 => Some(1),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    /// Addresses this instruction accesses through position-mode parameters.
+    pub fn position_operands(&self) -> impl Iterator<Item = usize> + '_ {
+        self.modes
+            .iter()
+            .zip(self.params)
+            .filter(|(mode, _)| matches!(mode, Mode::Position))
+            .filter_map(|(_, &p)| p.try_into().ok())
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self.opcode {
+            1 => "add",
+            2 => "mul",
+            3 => "in",
+            4 => "out",
+            5 => "jnz",
+            6 => "jz",
+            7 => "lt",
+            8 => "eq",
+            9 => "arb",
+            99 => "hlt",
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        for (i, (mode, &p)) in self.modes.iter().zip(self.params).enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            match mode {
+                Mode::Position => write!(f, "{}[{}]", sep, p)?,
+                Mode::Immediate => write!(f, "{}#{}", sep, p)?,
+                Mode::Relative => write!(f, "{}[rb{:+}]", sep, p)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode `mem` by linear sweep from address 0. Cells that don't decode are skipped.
+pub fn sweep(mem: &[isize]) -> Vec<Instruction<'_>> {
+    let mut insts = Vec::new();
+    let mut pc = 0;
+    while pc < mem.len() {
+        if let Some(inst) = Instruction::decode(mem, pc) {
+            pc += inst.size();
+            insts.push(inst);
+        } else {
+            pc += 1;
+        }
+    }
+    insts
+}
+
+/// Disassemble `len` cells of `mem` starting at `addr`, one line per instruction.
+/// Cells that don't decode are printed as data.
+pub fn listing(mem: &[isize], addr: usize, len: usize) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pc = addr;
+    while pc < (addr + len).min(mem.len()) {
+        if let Some(inst) = Instruction::decode(mem, pc) {
+            lines.push((pc, inst.to_string()));
+            pc += inst.size();
+        } else {
+            lines.push((pc, format!("data {}", mem[pc])));
+            pc += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_decode() {
+        let mem = [1002, 4, 3, 4, 33, 109, -1, 204, 1, 99];
+        let inst = Instruction::decode(&mem, 0).unwrap();
+        assert_eq!(inst.size(), 4);
+        assert_eq!(inst.to_string(), "mul [4], #3, [4]");
+        assert!(Instruction::decode(&mem, 4).is_none());
+        assert_eq!(Instruction::decode(&mem, 5).unwrap().to_string(), "arb #-1");
+        assert_eq!(
+            Instruction::decode(&mem, 7).unwrap().to_string(),
+            "out [rb+1]"
+        );
+        assert_eq!(Instruction::decode(&mem, 9).unwrap().to_string(), "hlt");
+    }
+
+    #[test]
+    fn test_listing() {
+        let mem = [1101, 1, 2, 5, 99, 0];
+        assert_eq!(listing(&mem, 0, mem.len()), vec![
+            (0, "add #1, #2, [5]".to_string()),
+            (4, "hlt".to_string()),
+            (5, "data 0".to_string()),
+        ]);
+    }
+}
@@ -1,10 +1,54 @@
-use std::{
-    collections::VecDeque,
-    fmt,
-    task::{Poll, ready},
-};
+#![no_std]
 
-use log::debug;
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{fmt, task::Poll};
+
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        log::debug!($($arg)*)
+    };
+}
+
+// Type-check the arguments, and log nothing
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "std")]
+pub mod cli;
+pub mod compiled;
+pub mod coverage;
+mod cycle;
+pub mod device;
+pub mod diff;
+pub mod disasm;
+pub mod link;
+pub mod memory;
+pub mod patch;
+pub mod script;
+mod snapshot;
+pub mod stack;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod threaded;
+pub mod transpile;
+
+use coverage::Coverage;
+use cycle::CycleDetector;
+use device::Mapping;
+use memory::Memory;
 
 pub fn parse_program(input: &str) -> Vec<isize> {
     input
@@ -15,19 +59,49 @@ pub fn parse_program(input: &str) -> Vec<isize> {
 }
 
 #[derive(Clone)]
-pub struct VM {
-    mem: Vec<isize>,
+pub struct VM<M = Vec<isize>> {
+    mem: M,
     pc: usize,
     relative_base: usize,
     input: VecDeque<isize>,
     output: VecDeque<isize>,
+    coverage: Option<Coverage>,
+    cycles: Option<CycleDetector>,
+    devices: Vec<Mapping>,
 }
 
 pub enum VMError {
     Halt,
 }
 
-enum Mode {
+/// Why [`VM::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Blocked on an input instruction with an empty input queue.
+    NeedsInput,
+    /// An output instruction was executed. Only returned by [`VM::run_until_output`].
+    Output,
+    /// Reached a halt instruction.
+    Halted,
+    /// Executed the number of steps given to [`VM::run_limit`].
+    LimitReached,
+    /// The machine state repeated without any I/O, so the program will never make progress.
+    /// Holds the pc where the loop is entered. Only returned after
+    /// [`VM::enable_loop_detection`].
+    InfiniteLoop(usize),
+}
+
+impl Status {
+    pub fn is_halted(&self) -> bool {
+        *self == Status::Halted
+    }
+
+    pub fn needs_input(&self) -> bool {
+        *self == Status::NeedsInput
+    }
+}
+
+pub(crate) enum Mode {
     Position,
     Immediate,
     Relative,
@@ -44,16 +118,6 @@ impl fmt::Display for Mode {
 }
 
 impl VM {
-    pub fn init(code: Vec<isize>) -> Self {
-        VM {
-            mem: code,
-            pc: 0,
-            relative_base: 0,
-            input: VecDeque::new(),
-            output: VecDeque::new(),
-        }
-    }
-
     fn mode(n: usize) -> Result<Mode, ()> {
         match n {
             0 => Ok(Mode::Position),
@@ -63,7 +127,7 @@ impl VM {
         }
     }
 
-    fn decode(op: isize) -> Result<(Mode, Mode, Mode, usize), ()> {
+    pub(crate) fn decode(op: isize) -> Result<(Mode, Mode, Mode, usize), ()> {
         let mut op: usize = op.try_into().map_err(|_| ())?;
         let opcode = op % 100;
         op /= 100;
@@ -74,6 +138,50 @@ impl VM {
         let c = op % 10;
         Ok((Self::mode(a)?, Self::mode(b)?, Self::mode(c)?, opcode))
     }
+}
+
+impl<M: Memory> VM<M> {
+    pub fn init(code: M) -> Self {
+        VM {
+            mem: code,
+            pc: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            coverage: None,
+            cycles: None,
+            devices: Vec::new(),
+        }
+    }
+
+    /// Start recording which addresses are executed, read and written from now on.
+    pub fn enable_coverage(&mut self) {
+        self.coverage.get_or_insert_with(Coverage::new);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Stop runs with [`Status::InfiniteLoop`] when the program loops without any I/O. The
+    /// memory is compared by fingerprint, and accesses to mapped devices count as I/O.
+    pub fn enable_loop_detection(&mut self) {
+        if self.cycles.is_none() {
+            self.cycles = Some(CycleDetector::new(
+                self.mem.cells(),
+                self.pc,
+                self.relative_base,
+            ));
+        }
+    }
+
+    pub fn disable_loop_detection(&mut self) {
+        self.cycles = None;
+    }
 
     pub fn write_port(&mut self, buf: &[isize]) {
         self.input.extend(buf);
@@ -99,13 +207,25 @@ impl VM {
     }
 
     fn step(&mut self) -> Poll<Result<(), VMError>> {
-        let n = self.mem[self.pc];
-        let (mode1, mode2, mode3, op) = if let Ok(dec) = Self::decode(n) {
+        let n = self.read_at(self.pc);
+        let (mode1, mode2, mode3, op) = if let Ok(dec) = VM::decode(n) {
             dec
         } else {
-            panic!("Invalid opcode {} at addr {}", n, self.pc);
+            panic!(
+                "Invalid opcode {} at addr {}\n{}",
+                n,
+                self.pc,
+                self.backtrace()
+            );
         };
 
+        // An input instruction that is waiting for input has not been executed yet
+        if let Some(coverage) = &mut self.coverage
+            && (op != 3 || !self.input.is_empty())
+        {
+            coverage.record_exec(self.pc);
+        }
+
         if op == 99 {
             debug!("[99]");
             debug!("Halt");
@@ -113,7 +233,7 @@ impl VM {
         }
         match op {
             1 | 2 | 7 | 8 => {
-                debug!("{:?}", &self.mem[self.pc..self.pc + 4]);
+                debug!("{:?}", &self.mem.cells()[self.pc..self.pc + 4]);
                 debug!("{} {}{}{}", op, mode1, mode2, mode3);
                 let x = self.read(mode1, 1);
                 let y = self.read(mode2, 2);
@@ -140,7 +260,7 @@ impl VM {
                 self.pc += 4;
             }
             3 => {
-                debug!("{:?}", &self.mem[self.pc..self.pc + 2]);
+                debug!("{:?}", &self.mem.cells()[self.pc..self.pc + 2]);
                 debug!("{} {}", op, mode1);
                 let v = if let Some(v) = self.input.pop_front() {
                     v
@@ -152,7 +272,7 @@ impl VM {
                 self.pc += 2;
             }
             4 => {
-                debug!("{:?}", &self.mem[self.pc..self.pc + 2]);
+                debug!("{:?}", &self.mem.cells()[self.pc..self.pc + 2]);
                 debug!("{} {}", op, mode1);
                 let v = self.read(mode1, 1);
                 debug!("Write output: {}", v);
@@ -160,7 +280,7 @@ impl VM {
                 self.pc += 2;
             }
             5 | 6 => {
-                debug!("{:?}", &self.mem[self.pc..self.pc + 3]);
+                debug!("{:?}", &self.mem.cells()[self.pc..self.pc + 3]);
                 debug!("{} {}{}", op, mode1, mode2);
                 let x = self.read(mode1, 1);
                 let addr = self.read(mode2, 2);
@@ -177,7 +297,7 @@ impl VM {
                 }
             }
             9 => {
-                debug!("{:?}", &self.mem[self.pc..self.pc + 2]);
+                debug!("{:?}", &self.mem.cells()[self.pc..self.pc + 2]);
                 debug!("{} {}", op, mode1);
                 let offset = self.read(mode1, 1);
                 self.relative_base =
@@ -193,20 +313,60 @@ impl VM {
                     };
                 self.pc += 2;
             }
-            _ => unimplemented!("Unknown instruction {}", op),
+            _ => unimplemented!("Unknown instruction {}\n{}", op, self.backtrace()),
         }
         Poll::Ready(Ok(()))
     }
 
-    pub fn run(&mut self) -> Poll<()> {
-        while let Ok(()) = ready!(self.step()) {
-            ()
+    fn run_with(&mut self, mut limit: Option<usize>, until_output: bool) -> Status {
+        loop {
+            if let Some(n) = &mut limit {
+                if *n == 0 {
+                    return Status::LimitReached;
+                }
+                *n -= 1;
+            }
+            let output_len = self.output.len();
+            let input_len = self.input.len();
+            match self.step() {
+                Poll::Pending => return Status::NeedsInput,
+                Poll::Ready(Err(VMError::Halt)) => return Status::Halted,
+                Poll::Ready(Ok(())) => {
+                    if until_output && self.output.len() > output_len {
+                        return Status::Output;
+                    }
+                }
+            }
+            if let Some(cycles) = &mut self.cycles {
+                let io = cycles.take_device_io();
+                if io || self.output.len() != output_len || self.input.len() != input_len {
+                    cycles.reset(self.pc, self.relative_base);
+                } else if let Some(length) = cycles.check(self.pc, self.relative_base) {
+                    let pc = self.loop_entry(length);
+                    debug!("Infinite loop of {} steps at {}", length, pc);
+                    return Status::InfiniteLoop(pc);
+                }
+            }
         }
-        Poll::Ready(())
+    }
+
+    /// Run until the program halts or needs input.
+    pub fn run(&mut self) -> Status {
+        self.run_with(None, false)
+    }
+
+    /// Run at most `limit` instructions.
+    pub fn run_limit(&mut self, limit: usize) -> Status {
+        self.run_with(Some(limit), false)
+    }
+
+    /// Run until the next output instruction, so each output can be handled as it comes.
+    pub fn run_until_output(&mut self) -> Status {
+        self.run_with(None, true)
     }
 
     pub fn run_ready(mut self) -> Vec<isize> {
-        if let Poll::Ready(()) = self.run() {
+        if self.run().is_halted() {
             self.output.into()
         } else {
             panic!("Program is pending");
@@ -238,21 +398,28 @@ impl VM {
         }
     }
 
-    fn read(&self, mode: Mode, offset: usize) -> isize {
+    fn read(&mut self, mode: Mode, offset: usize) -> isize {
         if let Mode::Immediate = mode {
             let ptr = self.read_at(self.pc + offset);
             debug!("Imm {}", ptr);
             ptr
         } else {
             let ptr = self.get_ptr(mode, offset);
-            let val = self.read_at(ptr);
+            let val = if self.devices.is_empty() {
+                self.read_at(ptr)
+            } else {
+                self.device_read(ptr).unwrap_or_else(|| self.read_at(ptr))
+            };
             debug!("Read[{}]: {}", ptr, val);
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record_read(ptr);
+            }
             val
         }
     }
 
     pub fn read_at(&self, addr: usize) -> isize {
-        self.mem.get(addr).copied().unwrap_or(0)
+        self.mem.cells().get(addr).copied().unwrap_or(0)
     }
 
     fn write(&mut self, mode: Mode, offset: usize, val: isize) {
@@ -264,90 +431,72 @@ impl VM {
             );
         } else {
             let ptr = self.get_ptr(mode, offset);
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record_write(ptr);
+            }
+            if !self.devices.is_empty() && self.device_write(ptr, val) {
+                return;
+            }
             self.write_at(ptr, val);
         }
     }
 
     pub fn write_at(&mut self, addr: usize, val: isize) {
         debug!("Write[{}]={}", addr, val);
-        if self.mem.len() <= addr {
-            self.mem.resize(addr + 1, 0);
+        if let Some(cycles) = &mut self.cycles {
+            cycles.record_write(addr, self.mem.cells().get(addr).copied().unwrap_or(0), val);
         }
-        self.mem[addr] = val;
+        self.mem.store(addr, val);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::VM;
+    use super::{Status, VM};
+    use crate::testing::{Transcript, assert_transcript};
+    use alloc::{string::ToString, vec};
 
     #[test]
     fn test_cmp() {
         // Using position mode, consider whether the input is equal to 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(
-            test_run(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[8]),
-            vec![1]
-        );
-        assert_eq!(
-            test_run(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[7]),
-            vec![0]
-        );
+        test_run(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[8], &[1]);
+        test_run(&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], &[7], &[0]);
 
         // Using position mode, consider whether the input is less than 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(
-            test_run(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[8]),
-            vec![0]
-        );
-        assert_eq!(
-            test_run(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[7]),
-            vec![1]
-        );
+        test_run(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[8], &[0]);
+        test_run(&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], &[7], &[1]);
 
         // Using immediate mode, consider whether the input is equal to 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(test_run(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], &[8]), vec![
-            1
-        ]);
-        assert_eq!(test_run(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], &[7]), vec![
-            0
-        ]);
+        test_run(&[3, 3, 1108, -1, 8, 3, 4, 3, 99], &[8], &[1]);
+        test_run(&[3, 3, 1108, -1, 8, 3, 4, 3, 99], &[7], &[0]);
 
         // Using immediate mode, consider whether the input is less than 8; output 1 (if it is) or 0 (if it is not).
-        assert_eq!(test_run(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], &[8]), vec![
-            0
-        ]);
-        assert_eq!(test_run(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], &[7]), vec![
-            1
-        ]);
+        test_run(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[8], &[0]);
+        test_run(&[3, 3, 1107, -1, 8, 3, 4, 3, 99], &[7], &[1]);
     }
 
     // Here are some jump tests that take an input, then output 0 if the input was zero or 1 if the input was non-zero.
     #[test]
     fn test_jmp() {
         // using position mode
-        assert_eq!(
-            test_run(
-                vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
-                &[0]
-            ),
-            vec![0]
+        test_run(
+            &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            &[0],
+            &[0],
         );
-        assert_eq!(
-            test_run(
-                vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
-                &[2]
-            ),
-            vec![1]
+        test_run(
+            &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            &[2],
+            &[1],
         );
 
         // using immediate mode
-        assert_eq!(
-            test_run(vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[0]),
-            vec![0]
-        );
-        assert_eq!(
-            test_run(vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[2]),
-            vec![1]
-        );
+        test_run(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[0], &[
+            0,
+        ]);
+        test_run(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1], &[2], &[
+            1,
+        ]);
     }
 
     #[test]
@@ -355,15 +504,15 @@ mod test {
         let program = vec![
             109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
         ];
-        assert_eq!(test_run(program.clone(), &[]), program);
+        test_run(&program, &[], &program);
 
         let program = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
-        let out = test_run(program, &[]);
+        let out = VM::init(program).run_ready();
         assert_eq!(out[0].to_string().len(), 16);
 
         let n = 1125899906842624;
         let program = vec![104, n, 99];
-        assert_eq!(test_run(program, &[]), vec![n]);
+        test_run(&program, &[], &[n]);
     }
 
     #[test]
@@ -375,15 +524,29 @@ mod test {
             20, 1105, 1, 46, 98, 99,
         ];
 
-        assert_eq!(test_run(program.clone(), &[7]), vec![999]);
-        assert_eq!(test_run(program.clone(), &[8]), vec![1000]);
-        assert_eq!(test_run(program.clone(), &[9]), vec![1001]);
+        test_run(&program, &[7], &[999]);
+        test_run(&program, &[8], &[1000]);
+        test_run(&program, &[9], &[1001]);
+    }
+
+    #[test]
+    fn test_status() {
+        // Echo the input twice, then halt
+        let program = vec![3, 9, 4, 9, 4, 9, 99];
+        let mut vm = VM::init(program);
+        assert_eq!(vm.run(), Status::NeedsInput);
+        vm.write_port(&[5]);
+        assert_eq!(vm.run_limit(1), Status::LimitReached);
+        assert_eq!(vm.run_until_output(), Status::Output);
+        assert_eq!(vm.read_all(), vec![5]);
+        assert_eq!(vm.run_until_output(), Status::Output);
+        assert_eq!(vm.run_until_output(), Status::Halted);
+        assert_eq!(vm.run(), Status::Halted);
+        assert_eq!(vm.read_all(), vec![5]);
     }
 
-    #[cfg(test)]
-    fn test_run(code: Vec<isize>, input: &[isize]) -> Vec<isize> {
-        let mut vm = VM::init(code);
-        vm.write_port(input);
-        vm.run_ready()
+    #[track_caller]
+    fn test_run(code: &[isize], input: &[isize], output: &[isize]) {
+        assert_transcript(code, &Transcript::new().input(input).output(output).halt());
     }
 }
@@ -0,0 +1,201 @@
+//! Object files and a linker, to build one program out of several modules.
+//!
+//! An object holds code assembled at address 0, the symbols it defines, and relocations for
+//! the cells that hold absolute addresses. In the text format, each line is one of:
+//!
+//! ```text
+//! name print            name of the object, for error messages
+//! code 109,2,204,-1     code cells, appended to the previous ones
+//! symbol print 0        `print` is defined at offset 0
+//! reloc 7               cell 7 holds an address in this object
+//! reloc 12 print        add the address of `print` to cell 12
+//! ```
+//!
+//! Lines starting with `#` are comments.
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Add the address the object is loaded at.
+    Local,
+    /// Add the address of a symbol.
+    Symbol(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: usize,
+    pub target: Target,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object {
+    pub name: String,
+    pub code: Vec<isize>,
+    /// Offsets of the symbols defined by this object
+    pub symbols: BTreeMap<String, usize>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// Lay out `objects` one after another from address 0, and resolve their relocations. The
+/// program starts with the code of the first object.
+pub fn link(objects: &[Object]) -> Result<Vec<isize>, String> {
+    let mut bases = Vec::with_capacity(objects.len());
+    let mut symbols: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut len = 0;
+    for obj in objects {
+        for (name, &offset) in &obj.symbols {
+            if offset > obj.code.len() {
+                return Err(format!(
+                    "{}: symbol {:?} at {} is past the end of the code",
+                    obj.name, name, offset
+                ));
+            }
+            if symbols.insert(name, len + offset).is_some() {
+                return Err(format!("{}: duplicate symbol {:?}", obj.name, name));
+            }
+        }
+        bases.push(len);
+        len += obj.code.len();
+    }
+
+    let mut program = Vec::with_capacity(len);
+    for (obj, &base) in objects.iter().zip(&bases) {
+        let mut code = obj.code.clone();
+        for reloc in &obj.relocations {
+            let addr = match &reloc.target {
+                Target::Local => base,
+                Target::Symbol(name) => *symbols
+                    .get(name.as_str())
+                    .ok_or_else(|| format!("{}: undefined symbol {:?}", obj.name, name))?,
+            };
+            let cell = code.get_mut(reloc.offset).ok_or_else(|| {
+                format!(
+                    "{}: relocation at {} is past the end of the code",
+                    obj.name, reloc.offset
+                )
+            })?;
+            *cell += addr as isize;
+        }
+        program.extend(code);
+    }
+    Ok(program)
+}
+
+impl FromStr for Object {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut obj = Object::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Line {}: invalid line {:?}", i + 1, line);
+            let parse_usize = |s: &str| s.parse::<usize>().map_err(|_| invalid());
+            let (keyword, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let args: Vec<&str> = arg.split_whitespace().collect();
+            match (keyword, &args[..]) {
+                ("name", [name]) => obj.name = name.to_string(),
+                ("code", _) => {
+                    for v in arg.split(',') {
+                        obj.code.push(v.trim().parse().map_err(|_| invalid())?);
+                    }
+                }
+                ("symbol", [name, offset]) => {
+                    let offset = parse_usize(offset)?;
+                    if obj.symbols.insert(name.to_string(), offset).is_some() {
+                        return Err(format!("Line {}: duplicate symbol {:?}", i + 1, name));
+                    }
+                }
+                ("reloc", [offset]) => obj.relocations.push(Relocation {
+                    offset: parse_usize(offset)?,
+                    target: Target::Local,
+                }),
+                ("reloc", [offset, name]) => obj.relocations.push(Relocation {
+                    offset: parse_usize(offset)?,
+                    target: Target::Symbol(name.to_string()),
+                }),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(obj)
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.name.is_empty() {
+            writeln!(f, "name {}", self.name)?;
+        }
+        let code: Vec<String> = self.code.iter().map(|v| v.to_string()).collect();
+        writeln!(f, "code {}", code.join(","))?;
+        for (name, offset) in &self.symbols {
+            writeln!(f, "symbol {} {}", name, offset)?;
+        }
+        for reloc in &self.relocations {
+            match &reloc.target {
+                Target::Local => writeln!(f, "reloc {}", reloc.offset)?,
+                Target::Symbol(name) => writeln!(f, "reloc {} {}", reloc.offset, name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{string::ToString, vec};
+
+    use super::{Object, link};
+    use crate::VM;
+
+    // Call `out2` with 7, then output the number of calls
+    const MAIN: &str = "\
+name main
+code 109,100, 21101,0,13,0, 21101,0,7,1, 1105,1,0
+code 4,0, 99
+reloc 4
+reloc 12 out2
+reloc 14 calls
+";
+
+    // Output the argument twice, and count the calls
+    const LIB: &str = "\
+name lib
+code 109,2, 204,-1, 204,-1, 1001,15,1,15, 109,-2, 2105,1,0, 0
+symbol calls 15
+symbol out2 0
+reloc 7
+reloc 9
+";
+
+    #[test]
+    fn test_link() {
+        let main: Object = MAIN.parse().unwrap();
+        let lib: Object = LIB.parse().unwrap();
+        assert_eq!(lib.to_string().parse::<Object>().unwrap(), lib);
+
+        let program = link(&[main.clone(), lib.clone()]).unwrap();
+        assert_eq!(program.len(), 32);
+        assert_eq!(&program[6..13], &[21101, 0, 7, 1, 1105, 1, 16]);
+        assert_eq!(&program[22..26], &[1001, 31, 1, 31]);
+        assert_eq!(VM::init(program).run_ready(), vec![7, 7, 1]);
+
+        assert!(link(&[main]).unwrap_err().contains("undefined symbol"));
+        assert!(
+            link(&[lib.clone(), lib])
+                .unwrap_err()
+                .contains("duplicate symbol")
+        );
+        assert!("reloc x".parse::<Object>().is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Memory backends of the [`VM`](crate::VM).
+
+use alloc::vec::Vec;
+
+pub trait Memory {
+    /// Memory up to the highest address written so far. Addresses past the end read as 0.
+    fn cells(&self) -> &[isize];
+
+    /// Write `val` at `addr`, extending the memory as needed.
+    fn store(&mut self, addr: usize, val: isize);
+}
+
+impl Memory for Vec<isize> {
+    fn cells(&self) -> &[isize] {
+        self
+    }
+
+    fn store(&mut self, addr: usize, val: isize) {
+        if self.len() <= addr {
+            self.resize(addr + 1, 0);
+        }
+        self[addr] = val;
+    }
+}
+
+/// Memory of at most `N` cells, which needs no allocator. Writing past the capacity panics.
+#[derive(Clone)]
+pub struct FixedMemory<const N: usize> {
+    cells: [isize; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedMemory<N> {
+    pub fn new(program: &[isize]) -> Self {
+        if program.len() > N {
+            panic!(
+                "Program of {} cells does not fit in fixed memory of {}",
+                program.len(),
+                N
+            );
+        }
+        let mut cells = [0; N];
+        cells[..program.len()].copy_from_slice(program);
+        FixedMemory {
+            cells,
+            len: program.len(),
+        }
+    }
+}
+
+impl<const N: usize> Memory for FixedMemory<N> {
+    fn cells(&self) -> &[isize] {
+        &self.cells[..self.len]
+    }
+
+    fn store(&mut self, addr: usize, val: isize) {
+        if addr >= N {
+            panic!("Write to addr {} past fixed memory of {}", addr, N);
+        }
+        self.cells[addr] = val;
+        self.len = self.len.max(addr + 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FixedMemory;
+    use crate::VM;
+
+    #[test]
+    fn test_fixed() {
+        // Output the input plus 1, through a cell past the end of the program
+        let mut vm = VM::init(FixedMemory::<16>::new(&[3, 10, 1001, 10, 1, 10, 4, 10, 99]));
+        vm.write_port(&[41]);
+        assert!(vm.run().is_halted());
+        assert_eq!(vm.read_port(), Some(42));
+        assert_eq!(vm.read_at(10), 42);
+        assert_eq!(vm.read_at(15), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_fixed_overflow() {
+        let mut vm = VM::init(FixedMemory::<8>::new(&[1101, 1, 1, 8, 99]));
+        vm.run();
+    }
+}
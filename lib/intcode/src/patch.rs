@@ -0,0 +1,231 @@
+//! Named sets of program patches.
+//!
+//! A patch file has one `[name]` header per set, followed by one patch per line:
+//!
+//! ```text
+//! # Insert two quarters
+//! [free-play]
+//! 0 = 1 -> 2
+//! ```
+//!
+//! `ADDR = NEW` writes NEW to ADDR. `ADDR = OLD -> NEW` does the same, but only if ADDR holds
+//! OLD, so a set written for one program fails on a different one. Lines starting with `#`
+//! are comments.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Patch {
+    pub addr: usize,
+    /// Value expected at `addr` before patching
+    pub old: Option<isize>,
+    pub new: isize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchSet {
+    pub name: String,
+    pub patches: Vec<Patch>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchFile {
+    pub sets: Vec<PatchSet>,
+}
+
+impl PatchSet {
+    /// Apply every patch to `program`. Nothing is written if any expected old value does not
+    /// match.
+    pub fn apply(&self, program: &mut Vec<isize>) -> Result<(), String> {
+        for patch in &self.patches {
+            let cur = program.get(patch.addr).copied().unwrap_or(0);
+            if let Some(old) = patch.old
+                && old != cur
+            {
+                return Err(format!(
+                    "Patch set {:?} expects {} at addr {}, found {}",
+                    self.name, old, patch.addr, cur
+                ));
+            }
+        }
+        for patch in &self.patches {
+            if program.len() <= patch.addr {
+                program.resize(patch.addr + 1, 0);
+            }
+            program[patch.addr] = patch.new;
+        }
+        Ok(())
+    }
+}
+
+impl PatchFile {
+    pub fn get(&self, name: &str) -> Option<&PatchSet> {
+        self.sets.iter().find(|set| set.name == name)
+    }
+
+    /// Apply the set `name` to `program`.
+    pub fn apply(&self, name: &str, program: &mut Vec<isize>) -> Result<(), String> {
+        self.get(name)
+            .ok_or_else(|| format!("No patch set {:?}", name))?
+            .apply(program)
+    }
+}
+
+#[cfg(feature = "std")]
+impl PatchFile {
+    /// Read the patch file at `path`. Errors name the file.
+    pub fn read(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .map_err(|e| file_error(path, e.kind(), e))?
+            .parse()
+            .map_err(|e| file_error(path, std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Apply the set `name` of the patch file at `path` to `program`.
+    pub fn apply_from(
+        path: impl AsRef<std::path::Path>,
+        name: &str,
+        program: &mut Vec<isize>,
+    ) -> std::io::Result<()> {
+        let path = path.as_ref();
+        Self::read(path)?
+            .apply(name, program)
+            .map_err(|e| file_error(path, std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(feature = "std")]
+fn file_error(
+    path: &std::path::Path,
+    kind: std::io::ErrorKind,
+    e: impl fmt::Display,
+) -> std::io::Error {
+    std::io::Error::new(kind, format!("{}: {}", path.display(), e))
+}
+
+impl FromStr for Patch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid patch {:?}, expected ADDR = [OLD ->] NEW", s);
+        let (addr, val) = s.split_once('=').ok_or_else(invalid)?;
+        let (old, new) = match val.split_once("->") {
+            Some((old, new)) => (Some(old.trim().parse().map_err(|_| invalid())?), new),
+            None => (None, val),
+        };
+        Ok(Patch {
+            addr: addr.trim().parse().map_err(|_| invalid())?,
+            old,
+            new: new.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl FromStr for PatchFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut file = PatchFile::default();
+        for (lineno, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let name = name.trim();
+                if file.get(name).is_some() {
+                    return Err(format!("Duplicate patch set {:?}", name));
+                }
+                file.sets.push(PatchSet {
+                    name: name.to_string(),
+                    patches: Vec::new(),
+                });
+                continue;
+            }
+            let set = file
+                .sets
+                .last_mut()
+                .ok_or_else(|| format!("Line {}: patch outside of a [set]", lineno + 1))?;
+            set.patches.push(
+                line.parse()
+                    .map_err(|e| format!("Line {}: {}", lineno + 1, e))?,
+            );
+        }
+        Ok(file)
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.old {
+            Some(old) => write!(f, "{} = {} -> {}", self.addr, old, self.new),
+            None => write!(f, "{} = {}", self.addr, self.new),
+        }
+    }
+}
+
+impl fmt::Display for PatchFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, set) in self.sets.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", set.name)?;
+            for patch in &set.patches {
+                writeln!(f, "{}", patch)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{string::ToString, vec};
+
+    use super::{Patch, PatchFile};
+
+    #[test]
+    fn test_patch_file() {
+        let file: PatchFile = "# comment\n[gravity]\n1 = 0 -> 12\n2 = 2\n\n[coin]\n0 = 1 -> 2\n"
+            .parse()
+            .unwrap();
+        assert_eq!(file.sets.len(), 2);
+        assert_eq!(file.get("gravity").unwrap().patches, vec![
+            Patch {
+                addr: 1,
+                old: Some(0),
+                new: 12
+            },
+            Patch {
+                addr: 2,
+                old: None,
+                new: 2
+            },
+        ]);
+        assert_eq!(
+            file.to_string(),
+            "[gravity]\n1 = 0 -> 12\n2 = 2\n\n[coin]\n0 = 1 -> 2\n"
+        );
+        assert_eq!(file.to_string().parse::<PatchFile>().unwrap(), file);
+
+        let mut program = vec![1, 0, 0, 3, 99];
+        file.apply("gravity", &mut program).unwrap();
+        assert_eq!(program, vec![1, 12, 2, 3, 99]);
+
+        // Already patched, so the expected old value doesn't match
+        assert!(file.apply("gravity", &mut program).is_err());
+        assert_eq!(program, vec![1, 12, 2, 3, 99]);
+        assert!(file.apply("coin", &mut program).is_ok());
+        assert!(file.apply("missing", &mut program).is_err());
+
+        assert!("0 = 1".parse::<PatchFile>().is_err());
+        assert!("[a]\n0 = x".parse::<PatchFile>().is_err());
+    }
+}
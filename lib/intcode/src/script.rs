@@ -0,0 +1,175 @@
+//! Scripted input sessions.
+//!
+//! A script has one command per line. Lines starting with `#` are comments.
+//!
+//! ```text
+//! wait Command?        run until the output contains "Command?"
+//! send north           send "north" and a newline as ASCII
+//! values 1, -2, 3      send raw integers
+//! expect You can't go  run until the program needs input or halts, and check the output
+//! expect-value 42      same, and check for a value outside of the ASCII range
+//! halt                 run, and check that the program halts
+//! ```
+//!
+//! `wait` and `expect` only look at the output since the previous `wait` or `expect`.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::str::FromStr;
+
+use crate::{Status, VM, memory::Memory};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Send(String),
+    Values(Vec<isize>),
+    Wait(String),
+    Expect(String),
+    ExpectValue(isize),
+    Halt,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script {
+    /// Commands with their line numbers
+    pub commands: Vec<(usize, Command)>,
+}
+
+/// Output as text, with values outside of the ASCII range as `[n]`.
+pub fn render(output: &[isize]) -> String {
+    let mut s = String::new();
+    for &v in output {
+        match u8::try_from(v).ok().filter(u8::is_ascii) {
+            Some(b) => s.push(b as char),
+            None => s.push_str(&format!("[{}]", v)),
+        }
+    }
+    s
+}
+
+impl Script {
+    /// Run the script on `vm`, and return all the output.
+    pub fn run<M: Memory>(&self, vm: &mut VM<M>) -> Result<Vec<isize>, String> {
+        let mut output = Vec::new();
+        // Start of the output not checked yet
+        let mut mark = 0;
+        for (lineno, command) in &self.commands {
+            let fail = |msg: String, output: &[isize]| {
+                Err(format!(
+                    "Line {}: {}, output was:\n{}",
+                    lineno,
+                    msg,
+                    render(output)
+                ))
+            };
+            match command {
+                Command::Send(text) => {
+                    let buf: Vec<isize> = text.bytes().map(|b| b as isize).collect();
+                    vm.write_port(&buf);
+                    vm.write_port(&[b'\n' as isize]);
+                }
+                Command::Values(values) => vm.write_port(values),
+                Command::Wait(text) => loop {
+                    let status = vm.run_until_output();
+                    output.extend(vm.read_all());
+                    if render(&output[mark..]).contains(text.as_str()) {
+                        mark = output.len();
+                        break;
+                    }
+                    if status != Status::Output {
+                        return fail(format!("{:?} before {:?}", status, text), &output[mark..]);
+                    }
+                },
+                Command::Expect(text) => {
+                    vm.run();
+                    output.extend(vm.read_all());
+                    if !render(&output[mark..]).contains(text.as_str()) {
+                        return fail(format!("Expected {:?}", text), &output[mark..]);
+                    }
+                    mark = output.len();
+                }
+                Command::ExpectValue(value) => {
+                    vm.run();
+                    output.extend(vm.read_all());
+                    if !output[mark..].contains(value) {
+                        return fail(format!("Expected value {}", value), &output[mark..]);
+                    }
+                    mark = output.len();
+                }
+                Command::Halt => {
+                    let status = vm.run();
+                    output.extend(vm.read_all());
+                    if !status.is_halted() {
+                        return fail(format!("Expected halt, got {:?}", status), &output[mark..]);
+                    }
+                    mark = output.len();
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut script = Script::default();
+        for (i, line) in s.lines().enumerate() {
+            let lineno = i + 1;
+            let line = line.trim_start();
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let invalid = |e: String| format!("Line {}: {}", lineno, e);
+            let command = match keyword.trim_end() {
+                "send" => Command::Send(arg.to_string()),
+                "values" => Command::Values(
+                    arg.split(',')
+                        .map(|s| s.trim().parse().map_err(|e| invalid(format!("{}", e))))
+                        .collect::<Result<_, _>>()?,
+                ),
+                "wait" => Command::Wait(arg.to_string()),
+                "expect" => Command::Expect(arg.to_string()),
+                "expect-value" => {
+                    Command::ExpectValue(arg.trim().parse().map_err(|e| invalid(format!("{}", e)))?)
+                }
+                "halt" => Command::Halt,
+                k => return Err(invalid(format!("Unknown command {:?}", k))),
+            };
+            script.commands.push((lineno, command));
+        }
+        Ok(script)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Script;
+    use crate::{VM, testing::fixtures::ECHO};
+
+    #[test]
+    fn test_script() {
+        let script: Script = "# Greeting\nwait ?\nsend hello\nexpect hello\nvalues 120, 10\nhalt\n"
+            .parse()
+            .unwrap();
+        assert_eq!(script.commands.len(), 5);
+        let mut vm = VM::init(ECHO.to_vec());
+        let output = script.run(&mut vm).unwrap();
+        assert_eq!(super::render(&output), "? hello\n? x");
+
+        let script: Script = "send hi\nexpect bye\n".parse().unwrap();
+        let err = script.run(&mut VM::init(ECHO.to_vec())).unwrap_err();
+        assert_eq!(err, "Line 2: Expected \"bye\", output was:\n? hi\n? ");
+
+        let script: Script = "send x\nwait ?\nwait !\n".parse().unwrap();
+        assert!(script.run(&mut VM::init(ECHO.to_vec())).is_err());
+
+        assert!("jump 3".parse::<Script>().is_err());
+        assert!("values 1, x".parse::<Script>().is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use alloc::{
+    collections::VecDeque,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::VM;
+
+fn join(values: impl IntoIterator<Item = isize>) -> String {
+    values
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn split(line: &str) -> Result<Vec<isize>, String> {
+    line.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|e| format!("Invalid value {:?}: {}", s, e))
+        })
+        .collect()
+}
+
+impl VM {
+    /// Serialize the machine state (memory, registers and pending I/O) as text.
+    /// Coverage counters are not included.
+    pub fn snapshot(&self) -> String {
+        format!(
+            "pc {}\nrelative_base {}\ninput {}\noutput {}\nmem {}\n",
+            self.pc,
+            self.relative_base,
+            join(self.input.iter().copied()),
+            join(self.output.iter().copied()),
+            join(self.mem.iter().copied())
+        )
+    }
+
+    /// Restore a machine from the output of [`VM::snapshot`].
+    pub fn restore(snapshot: &str) -> Result<VM, String> {
+        let mut vm = VM::init(Vec::new());
+        let mut has_mem = false;
+        for line in snapshot.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "pc" => {
+                    vm.pc = value.parse().map_err(|e| format!("Invalid pc: {}", e))?;
+                }
+                "relative_base" => {
+                    vm.relative_base = value
+                        .parse()
+                        .map_err(|e| format!("Invalid relative_base: {}", e))?;
+                }
+                "input" => {
+                    vm.input = VecDeque::from(split(value)?);
+                }
+                "output" => {
+                    vm.output = VecDeque::from(split(value)?);
+                }
+                "mem" => {
+                    vm.mem = split(value)?;
+                    has_mem = true;
+                }
+                _ => return Err(format!("Unknown snapshot field {:?}", key)),
+            }
+        }
+        if has_mem {
+            Ok(vm)
+        } else {
+            Err("Snapshot has no memory".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::VM;
+    use alloc::vec;
+
+    #[test]
+    fn test_snapshot() {
+        let mut vm = VM::init(vec![3, 9, 4, 9, 109, 3, 3, 9, 99, 0]);
+        vm.write_port(&[42]);
+        assert!(vm.run().needs_input());
+        let snapshot = vm.snapshot();
+        assert_eq!(
+            snapshot,
+            "pc 6\nrelative_base 3\ninput \noutput 42\nmem 3,9,4,9,109,3,3,9,99,42\n"
+        );
+
+        let mut restored = VM::restore(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        restored.write_port(&[7]);
+        assert!(restored.run().is_halted());
+        assert_eq!(restored.read_all(), vec![42]);
+        assert_eq!(restored.read_at(9), 7);
+
+        assert!(VM::restore("pc 0\n").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Heuristic call-stack unwinding.
+//!
+//! Assumes the calling convention of the puzzle programs: the caller stores the return
+//! address at `[rb+0]` and the arguments after it, then jumps to the function. The function
+//! allocates its frame with `arb #k`, and returns with `arb #-k` followed by a jump through
+//! `[rb+0]`. The size of a frame is found from that epilogue, and a return address is only
+//! trusted if the instruction before it is a jump.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::{Mode, VM, disasm::Instruction, memory::Memory};
+
+/// How far to look ahead of the pc for the function epilogue.
+const SCAN_LIMIT: usize = 1000;
+const DEPTH_LIMIT: usize = 64;
+/// Number of frame cells shown as arguments.
+const ARG_LIMIT: usize = 4;
+
+pub struct Frame {
+    pub pc: usize,
+    pub relative_base: usize,
+    /// Number of cells allocated by the frame, including the return address. `None` for
+    /// the outermost frame, or where unwinding stopped.
+    pub size: Option<usize>,
+    /// Frame cells after the return address.
+    pub args: Vec<isize>,
+}
+
+pub struct Backtrace {
+    pub frames: Vec<Frame>,
+    mem: Vec<isize>,
+}
+
+impl<M: Memory> VM<M> {
+    /// Reconstruct the call stack from the current pc and relative base.
+    pub fn backtrace(&self) -> Backtrace {
+        let mut frames = Vec::new();
+        let (mut pc, mut rb) = (self.pc, self.relative_base);
+        while frames.len() < DEPTH_LIMIT {
+            let caller = frame_size(self.mem.cells(), pc)
+                .and_then(|size| Some((size, rb.checked_sub(size)?)))
+                .and_then(|(size, base)| {
+                    let ret = return_site(self.mem.cells(), self.read_at(base))?;
+                    Some((size, base, ret))
+                });
+            let Some((size, base, ret)) = caller else {
+                frames.push(Frame {
+                    pc,
+                    relative_base: rb,
+                    size: None,
+                    args: Vec::new(),
+                });
+                break;
+            };
+            let args = (base + 1..rb)
+                .take(ARG_LIMIT)
+                .map(|addr| self.read_at(addr))
+                .collect();
+            frames.push(Frame {
+                pc,
+                relative_base: rb,
+                size: Some(size),
+                args,
+            });
+            pc = ret;
+            rb = base;
+        }
+        Backtrace {
+            frames,
+            mem: self.mem.cells().to_vec(),
+        }
+    }
+}
+
+// `jnz #1, [rb+0]` or `jz #0, [rb+0]`
+fn is_return(inst: &Instruction) -> bool {
+    let taken = match (inst.opcode, &inst.modes[0], inst.params.first()) {
+        (5, Mode::Immediate, Some(&x)) => x != 0,
+        (6, Mode::Immediate, Some(&x)) => x == 0,
+        _ => false,
+    };
+    taken && matches!(inst.modes[1], Mode::Relative) && inst.params[1] == 0
+}
+
+// Size of the frame the code at `pc` runs in, from the first return ahead of it
+fn frame_size(mem: &[isize], pc: usize) -> Option<usize> {
+    let mut addr = pc;
+    let mut pop = 0;
+    while addr < pc + SCAN_LIMIT {
+        let Some(inst) = Instruction::decode(mem, addr) else {
+            addr += 1;
+            pop = 0;
+            continue;
+        };
+        if is_return(&inst) {
+            return Some(pop);
+        }
+        if inst.opcode == 99 {
+            return None;
+        }
+        pop = match (inst.opcode, &inst.modes[0], inst.params.first()) {
+            (9, Mode::Immediate, Some(&k)) if k < 0 => k.unsigned_abs(),
+            _ => 0,
+        };
+        addr += inst.size();
+    }
+    None
+}
+
+// A return address is right after the jump that made the call
+fn return_site(mem: &[isize], ret: isize) -> Option<usize> {
+    let ret: usize = ret.try_into().ok()?;
+    let call = Instruction::decode(mem, ret.checked_sub(3)?)?;
+    (matches!(call.opcode, 5 | 6) && ret < mem.len()).then_some(ret)
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            let inst = match Instruction::decode(&self.mem, frame.pc) {
+                Some(inst) => inst.to_string(),
+                None => format!("data {}", self.mem.get(frame.pc).copied().unwrap_or(0)),
+            };
+            write!(
+                f,
+                "#{:<2} {:6}: {:24} rb {}",
+                i, frame.pc, inst, frame.relative_base
+            )?;
+            if frame.size.is_some() {
+                let args: Vec<String> = frame.args.iter().map(|v| v.to_string()).collect();
+                write!(f, "  args {}", args.join(","))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::VM;
+    use alloc::{string::ToString, vec, vec::Vec};
+
+    #[test]
+    fn test_backtrace() {
+        let mut vm = VM::init(vec![
+            109, 100, // arb #100
+            21101, 0, 13, 0, // return address
+            21101, 0, 42, 1, // argument
+            1105, 1, 14, // call 14
+            99, //
+            109, 3, // arb #3
+            3, 200, // in [200]
+            109, -3, // arb #-3
+            2105, 1, 0, // return
+        ]);
+        assert!(vm.run().needs_input());
+        let bt = vm.backtrace();
+        let frames: Vec<_> = bt
+            .frames
+            .iter()
+            .map(|frame| {
+                (
+                    frame.pc,
+                    frame.relative_base,
+                    frame.size,
+                    frame.args.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(frames, vec![
+            (16, 103, Some(3), vec![42, 0]),
+            (13, 100, None, vec![])
+        ]);
+        assert_eq!(
+            bt.to_string(),
+            "#0      16: in [200]                 rb 103  args 42,0\n\
+             #1      13: hlt                      rb 100\n"
+        );
+
+        vm.write_port(&[1]);
+        assert!(vm.run().is_halted());
+    }
+}
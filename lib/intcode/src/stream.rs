@@ -0,0 +1,84 @@
+//! Async adapter: a [`VM`] as a [`Stream`] of outputs, fed by a stream of inputs.
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::{Status, VM};
+
+/// Yields the outputs of the VM. Input is pulled from `input` whenever the program blocks on
+/// an input instruction. The stream ends when the program halts, or when it needs input
+/// after `input` has ended.
+pub struct VMStream<S> {
+    vm: VM,
+    input: S,
+}
+
+impl<S> VMStream<S> {
+    pub fn new(vm: VM, input: S) -> Self {
+        VMStream { vm, input }
+    }
+
+    pub fn into_vm(self) -> VM {
+        self.vm
+    }
+}
+
+impl<S: Stream<Item = isize> + Unpin> Stream for VMStream<S> {
+    type Item = isize;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<isize>> {
+        let this = &mut *self;
+        loop {
+            if let Some(v) = this.vm.read_port() {
+                return Poll::Ready(Some(v));
+            }
+            match this.vm.run_until_output() {
+                Status::Output => (),
+                Status::Halted | Status::InfiniteLoop(_) => return Poll::Ready(None),
+                Status::NeedsInput => match Pin::new(&mut this.input).poll_next(cx) {
+                    Poll::Ready(Some(v)) => this.vm.write_port(&[v]),
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => return Poll::Pending,
+                },
+                Status::LimitReached => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{vec, vec::Vec};
+    use futures::{SinkExt, StreamExt, channel::mpsc, executor::block_on, stream};
+
+    use super::VMStream;
+    use crate::{VM, testing::fixtures::DOUBLE};
+
+    #[test]
+    fn test_chain() {
+        let input = stream::iter(vec![1, 2, 3, 0]);
+        let vm2 = VMStream::new(
+            VM::init(DOUBLE.to_vec()),
+            VMStream::new(VM::init(DOUBLE.to_vec()), input),
+        );
+        assert_eq!(block_on(vm2.collect::<Vec<_>>()), vec![4, 8, 12]);
+    }
+
+    #[test]
+    fn test_channel() {
+        let (mut tx, rx) = mpsc::unbounded();
+        let mut vm = VMStream::new(VM::init(DOUBLE.to_vec()), rx);
+        block_on(async {
+            tx.send(5).await.unwrap();
+            assert_eq!(vm.next().await, Some(10));
+            tx.send(7).await.unwrap();
+            assert_eq!(vm.next().await, Some(14));
+            drop(tx);
+            assert_eq!(vm.next().await, None);
+        });
+    }
+}
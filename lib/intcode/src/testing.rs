@@ -0,0 +1,368 @@
+//! Golden-transcript tests: run a program against recorded inputs and expected outputs, and
+//! report where it first diverges.
+//!
+//! In the text format each line is an input or an expected output, in order:
+//!
+//! ```text
+//! < 1, 2               numeric input
+//! > 3                  numeric output
+//! << north             ASCII input, followed by a newline
+//! >> == Hull Breach == ASCII output, followed by a newline
+//! halt                 the program halts after the last output
+//! ```
+//!
+//! Lines starting with `#` are comments.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+use crate::{Status, VM, script::render};
+
+/// Input, and the output the program produces before it waits for more input.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Exchange {
+    pub input: Vec<isize>,
+    pub output: Vec<isize>,
+    /// Show the output as text
+    pub ascii: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    pub exchanges: Vec<Exchange>,
+    /// The program halts after the last exchange.
+    pub halt: bool,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn exchange_for_input(&mut self) -> &mut Exchange {
+        if self.exchanges.last().is_none_or(|ex| !ex.output.is_empty()) {
+            self.exchanges.push(Exchange::default());
+        }
+        self.exchanges.last_mut().unwrap()
+    }
+
+    fn exchange_for_output(&mut self) -> &mut Exchange {
+        if self.exchanges.is_empty() {
+            self.exchanges.push(Exchange::default());
+        }
+        self.exchanges.last_mut().unwrap()
+    }
+
+    pub fn input(mut self, values: &[isize]) -> Self {
+        self.exchange_for_input().input.extend(values);
+        self
+    }
+
+    pub fn output(mut self, values: &[isize]) -> Self {
+        self.exchange_for_output().output.extend(values);
+        self
+    }
+
+    /// Send `line` and a newline as ASCII.
+    pub fn send(mut self, line: &str) -> Self {
+        let ex = self.exchange_for_input();
+        ex.input.extend(line.bytes().map(|b| b as isize));
+        ex.input.push(b'\n' as isize);
+        self
+    }
+
+    /// Expect `line` and a newline as ASCII.
+    pub fn expect(mut self, line: &str) -> Self {
+        let ex = self.exchange_for_output();
+        ex.output.extend(line.bytes().map(|b| b as isize));
+        ex.output.push(b'\n' as isize);
+        ex.ascii = true;
+        self
+    }
+
+    pub fn halt(mut self) -> Self {
+        self.halt = true;
+        self
+    }
+}
+
+/// Where a program first diverged from its transcript.
+pub struct Mismatch {
+    /// Index of the exchange
+    pub exchange: usize,
+    pub reason: String,
+    pub expected: Vec<isize>,
+    /// Output of the exchange up to the divergence
+    pub actual: Vec<isize>,
+    /// The machine right after the divergence
+    pub vm: Box<VM>,
+    /// What the machine goes on to output until it waits for input, for the diff
+    rest: Vec<isize>,
+    ascii: bool,
+}
+
+/// Instructions run after a divergence to complete the output of the exchange.
+const REST_LIMIT: usize = 1_000_000;
+
+/// Run `program` against `transcript`.
+pub fn check(program: &[isize], transcript: &Transcript) -> Result<(), Mismatch> {
+    let mut vm = VM::init(program.to_vec());
+    for (i, ex) in transcript.exchanges.iter().enumerate() {
+        vm.write_port(&ex.input);
+        let mut actual = Vec::new();
+        let reason = loop {
+            let status = vm.run_until_output();
+            if status == Status::Output {
+                let v = vm.read_port().unwrap();
+                actual.push(v);
+                match ex.output.get(actual.len() - 1) {
+                    Some(&e) if e == v => continue,
+                    Some(&e) => break format!("expected {}, got {}", e, v),
+                    None => break format!("unexpected output {}", v),
+                }
+            } else if actual.len() < ex.output.len() {
+                break format!(
+                    "{:?} with {} values left to output",
+                    status,
+                    ex.output.len() - actual.len()
+                );
+            } else if status.is_halted() && i + 1 < transcript.exchanges.len() {
+                break "halted before the end of the transcript".to_string();
+            } else if !status.is_halted() && transcript.halt && i + 1 == transcript.exchanges.len()
+            {
+                break format!("expected halt, got {:?}", status);
+            } else {
+                break String::new();
+            }
+        };
+        if !reason.is_empty() {
+            let mut rest = vm.clone();
+            rest.run_limit(REST_LIMIT);
+            return Err(Mismatch {
+                exchange: i,
+                reason,
+                expected: ex.output.clone(),
+                actual,
+                vm: Box::new(vm),
+                rest: rest.read_all(),
+                ascii: ex.ascii,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Panic with a readable report if `program` diverges from `transcript`.
+#[track_caller]
+pub fn assert_transcript(program: &[isize], transcript: &Transcript) {
+    if let Err(mismatch) = check(program, transcript) {
+        panic!("{}", mismatch);
+    }
+}
+
+/// Line diff of `expected` and `actual`, along their longest common subsequence of lines, so
+/// a changed line doesn't mark the rest of the output as changed too.
+fn diff(f: &mut fmt::Formatter, expected: &str, actual: &str) -> fmt::Result {
+    let expected: Vec<&str> = expected.split_inclusive('\n').collect();
+    let actual: Vec<&str> = actual.split_inclusive('\n').collect();
+    let (n, m) = (expected.len(), actual.len());
+    // Length of the longest common subsequence of `expected[i..]` and `actual[j..]`
+    let mut common = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            writeln!(f, "    {}", expected[i].trim_end_matches('\n'))?;
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+            writeln!(f, "  - {:?}", expected[i])?;
+            i += 1;
+        } else {
+            writeln!(f, "  + {:?}", actual[j])?;
+            j += 1;
+        }
+    }
+    Ok(())
+}
+
+fn join(values: &[isize]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Exchange {}: {}", self.exchange, self.reason)?;
+        if self.ascii {
+            let actual = [&self.actual[..], &self.rest].concat();
+            diff(f, &render(&self.expected), &render(&actual))?;
+        } else {
+            writeln!(f, "  expected: {}", join(&self.expected))?;
+            writeln!(f, "  actual:   {}", join(&self.actual))?;
+        }
+        writeln!(
+            f,
+            "VM at pc {}, relative base {}, pending input [{}]",
+            self.vm.pc,
+            self.vm.relative_base,
+            join(&self.vm.input.iter().copied().collect::<Vec<_>>())
+        )?;
+        write!(f, "{}", self.vm.backtrace())
+    }
+}
+
+impl fmt::Debug for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Write `values` as `prefix` lines: ASCII text line by line, the rest as numbers.
+fn write_values(
+    f: &mut fmt::Formatter,
+    prefix: &str,
+    values: &[isize],
+    ascii: bool,
+) -> fmt::Result {
+    let mut rest = values;
+    while ascii && !rest.is_empty() {
+        let Some(end) = rest.iter().position(|&v| v == b'\n' as isize) else {
+            break;
+        };
+        let line = &rest[..end];
+        if !line.iter().all(|&v| (0x20..0x7f).contains(&v)) {
+            break;
+        }
+        let text = render(line);
+        if text.is_empty() {
+            writeln!(f, "{}{}", prefix, prefix)?;
+        } else {
+            writeln!(f, "{}{} {}", prefix, prefix, text)?;
+        }
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        writeln!(f, "{} {}", prefix, join(rest).replace(',', ", "))?;
+    }
+    Ok(())
+}
+
+/// The text format read by [`FromStr`], with the input as ASCII lines when possible.
+impl fmt::Display for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ex in &self.exchanges {
+            write_values(f, "<", &ex.input, true)?;
+            write_values(f, ">", &ex.output, ex.ascii)?;
+        }
+        if self.halt {
+            writeln!(f, "halt")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Transcript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut transcript = Transcript::new();
+        for (i, line) in s.lines().enumerate() {
+            let values = |s: &str| {
+                s.split(',')
+                    .map(|v| v.trim().parse())
+                    .collect::<Result<Vec<isize>, _>>()
+                    .map_err(|e| format!("Line {}: {}", i + 1, e))
+            };
+            transcript = if let Some(text) = line.strip_prefix(">>") {
+                transcript.expect(text.strip_prefix(' ').unwrap_or(text))
+            } else if let Some(text) = line.strip_prefix("<<") {
+                transcript.send(text.strip_prefix(' ').unwrap_or(text))
+            } else if let Some(s) = line.strip_prefix('>') {
+                transcript.output(&values(s)?)
+            } else if let Some(s) = line.strip_prefix('<') {
+                transcript.input(&values(s)?)
+            } else if line.trim() == "halt" {
+                transcript.halt()
+            } else if line.trim().is_empty() || line.starts_with('#') {
+                transcript
+            } else {
+                return Err(format!("Line {}: invalid line {:?}", i + 1, line));
+            };
+        }
+        Ok(transcript)
+    }
+}
+
+/// Programs shared by the tests of this crate and of the days.
+#[cfg(any(test, feature = "testing"))]
+pub mod fixtures {
+    /// Prompt with "? " and echo the input back, until an "x"
+    pub const ECHO: &[isize] = &[
+        104, 63, 104, 32, 3, 100, 4, 100, 1008, 100, 120, 101, 1005, 101, 25, 1008, 100, 10, 101,
+        1005, 101, 0, 1105, 1, 4, 99,
+    ];
+
+    /// Output twice the input, until the input is 0
+    pub const DOUBLE: &[isize] = &[
+        3, 100, 1006, 100, 14, 1002, 100, 2, 101, 4, 101, 1105, 1, 0, 99,
+    ];
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+
+    use super::{Transcript, check, fixtures::ECHO};
+
+    #[test]
+    fn test_transcript() {
+        let echo = ECHO;
+        let transcript: Transcript = "# Echo\n<< hi\n>> ? hi\n> 63, 32\n< 120\n> 120\nhalt\n"
+            .parse()
+            .unwrap();
+        assert_eq!(transcript.exchanges.len(), 2);
+        assert!(check(echo, &transcript).is_ok());
+        let text = transcript.to_string();
+        assert_eq!(text, "<< hi\n>> ? hi\n> 63, 32\n< 120\n> 120\nhalt\n");
+        assert_eq!(text.parse::<Transcript>().unwrap(), transcript);
+
+        let transcript = Transcript::new().send("ho").expect("? hi").halt();
+        let mismatch = check(echo, &transcript).unwrap_err();
+        assert_eq!(mismatch.exchange, 0);
+        assert_eq!(mismatch.reason, "expected 105, got 111");
+        assert_eq!(mismatch.vm.pc, 8);
+        let report = mismatch.to_string();
+        assert!(report.contains("  - \"? hi\\n\"\n  + \"? ho\\n\"\n  + \"? \"\n"));
+        assert!(report.contains("pending input [10]"));
+
+        // Only the changed line is marked
+        let transcript = Transcript::new()
+            .send("one\ntwo\nthree")
+            .expect("? one")
+            .expect("? too")
+            .expect("? three");
+        let report = check(echo, &transcript).unwrap_err().to_string();
+        assert!(report.contains("    ? one\n  - \"? too\\n\"\n  + \"? two\\n\"\n    ? three\n"));
+
+        let transcript = Transcript::new().input(&[120]).output(&[63, 32]).halt();
+        assert_eq!(transcript.to_string(), "< 120\n> 63, 32\nhalt\n");
+        let mismatch = check(echo, &transcript).unwrap_err();
+        assert_eq!(mismatch.reason, "unexpected output 120");
+    }
+}
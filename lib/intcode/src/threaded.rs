@@ -0,0 +1,264 @@
+//! Thread-per-VM runtime, as an alternative to driving [`VM::run`] by hand.
+//!
+//! Each VM runs on its own thread. Input is sent to a VM through [`Runtime::send`], and the
+//! outputs of all VMs are collected on one channel, tagged with the pid of the VM.
+
+use alloc::{format, vec::Vec};
+use std::{
+    sync::{
+        Arc, Condvar, Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{Status, VM};
+
+#[derive(Default)]
+struct Activity {
+    /// VMs waiting for input without having received any since, or halted
+    blocked: usize,
+    /// Inputs and outputs sent but not received yet
+    in_flight: usize,
+    /// Bumped whenever a VM receives input or produces output
+    epoch: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    activity: Mutex<Activity>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn update(&self, f: impl FnOnce(&mut Activity)) {
+        f(&mut self.activity.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+/// Input configuration of a VM thread.
+#[derive(Clone, Copy)]
+pub enum InputMode {
+    /// Block until input arrives.
+    Blocking,
+    /// Feed `value` when no input arrives within `timeout`. A VM that only ever gets the
+    /// default value still counts as blocked for idle detection.
+    Timeout { timeout: Duration, value: isize },
+}
+
+pub struct Runtime {
+    inputs: Vec<Sender<Vec<isize>>>,
+    output_tx: Sender<(usize, isize)>,
+    output: Receiver<(usize, isize)>,
+    handles: Vec<JoinHandle<VM>>,
+    shared: Arc<Shared>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        let (output_tx, output) = mpsc::channel();
+        Runtime {
+            inputs: Vec::new(),
+            output_tx,
+            output,
+            handles: Vec::new(),
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    /// Start `vm` on a new thread, and return its pid.
+    pub fn spawn(&mut self, vm: VM, mode: InputMode) -> usize {
+        let pid = self.handles.len();
+        let (input_tx, input) = mpsc::channel();
+        let output = self.output_tx.clone();
+        let shared = self.shared.clone();
+        let handle = thread::Builder::new()
+            .name(format!("vm-{}", pid))
+            .spawn(move || worker(pid, vm, mode, input, output, &shared))
+            .unwrap();
+        self.inputs.push(input_tx);
+        self.handles.push(handle);
+        pid
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Send input to the VM `pid`. The values are delivered together, so a VM with a
+    /// timeout never sees the default value in between. Input sent to a halted VM is dropped.
+    pub fn send(&self, pid: usize, values: &[isize]) {
+        self.shared.update(|a| a.in_flight += 1);
+        if self.inputs[pid].send(values.to_vec()).is_err() {
+            self.shared.update(|a| a.in_flight -= 1);
+        }
+    }
+
+    /// Receive the next output of any VM, as `(pid, value)`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<(usize, isize)> {
+        let out = self.output.recv_timeout(timeout).ok()?;
+        self.shared.update(|a| a.in_flight -= 1);
+        Some(out)
+    }
+
+    /// Every VM is blocked on input or halted, and there is no undelivered input or output.
+    pub fn is_idle(&self) -> bool {
+        let activity = self.shared.activity.lock().unwrap();
+        self.idle(&activity)
+    }
+
+    fn idle(&self, activity: &Activity) -> bool {
+        activity.blocked == self.handles.len() && activity.in_flight == 0
+    }
+
+    /// Wait up to `settle`, and return `true` if the runtime stayed idle for all of it.
+    /// Returns `false` as soon as it is not idle.
+    pub fn wait_idle(&self, settle: Duration) -> bool {
+        let activity = self.shared.activity.lock().unwrap();
+        if !self.idle(&activity) {
+            return false;
+        }
+        let epoch = activity.epoch;
+        let (activity, _) = self
+            .shared
+            .changed
+            .wait_timeout_while(activity, settle, |a| a.epoch == epoch && self.idle(a))
+            .unwrap();
+        activity.epoch == epoch && self.idle(&activity)
+    }
+
+    /// Close every input, and wait for the VMs to stop. VMs blocked on input stop right
+    /// away, VMs that never read input again keep this waiting forever.
+    pub fn join(self) -> Vec<VM> {
+        drop(self.inputs);
+        self.handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    }
+}
+
+fn worker(
+    pid: usize,
+    mut vm: VM,
+    mode: InputMode,
+    input: Receiver<Vec<isize>>,
+    output: Sender<(usize, isize)>,
+    shared: &Shared,
+) -> VM {
+    let mut blocked = false;
+    loop {
+        match vm.run_until_output() {
+            Status::Output => {
+                while let Some(v) = vm.read_port() {
+                    shared.update(|a| {
+                        a.in_flight += 1;
+                        a.epoch += 1;
+                        if blocked {
+                            a.blocked -= 1;
+                        }
+                    });
+                    blocked = false;
+                    if output.send((pid, v)).is_err() {
+                        shared.update(|a| a.in_flight -= 1);
+                    }
+                }
+            }
+            Status::Halted | Status::InfiniteLoop(_) => {
+                debug!("VM {} halted", pid);
+                if !blocked {
+                    shared.update(|a| a.blocked += 1);
+                }
+                return vm;
+            }
+            Status::NeedsInput => {
+                if !blocked {
+                    shared.update(|a| a.blocked += 1);
+                    blocked = true;
+                }
+                let v = match mode {
+                    InputMode::Blocking => input.recv().ok(),
+                    InputMode::Timeout { timeout, value } => match input.recv_timeout(timeout) {
+                        Ok(v) => Some(v),
+                        Err(RecvTimeoutError::Timeout) => {
+                            vm.write_port(&[value]);
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => None,
+                    },
+                };
+                let Some(v) = v else {
+                    return vm;
+                };
+                shared.update(|a| {
+                    a.in_flight -= 1;
+                    a.blocked -= 1;
+                    a.epoch += 1;
+                });
+                blocked = false;
+                vm.write_port(&v);
+            }
+            Status::LimitReached => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+    use std::time::Duration;
+
+    use super::{InputMode, Runtime};
+    use crate::{VM, testing::fixtures::DOUBLE};
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_runtime() {
+        let mut rt = Runtime::new();
+        let a = rt.spawn(VM::init(DOUBLE.to_vec()), InputMode::Blocking);
+        let b = rt.spawn(VM::init(DOUBLE.to_vec()), InputMode::Blocking);
+        rt.send(a, &[3]);
+        rt.send(b, &[4]);
+        let mut outputs = vec![rt.recv_timeout(WAIT), rt.recv_timeout(WAIT)];
+        outputs.sort();
+        assert_eq!(outputs, vec![Some((a, 6)), Some((b, 8))]);
+
+        rt.send(a, &[0]);
+        let vms = rt.join();
+        assert_eq!(vms.len(), 2);
+    }
+
+    #[test]
+    fn test_idle() {
+        let mut rt = Runtime::new();
+        let a = rt.spawn(VM::init(DOUBLE.to_vec()), InputMode::Blocking);
+        while !rt.wait_idle(Duration::from_millis(20)) {}
+        rt.send(a, &[21]);
+        assert!(!rt.is_idle());
+        assert_eq!(rt.recv_timeout(WAIT), Some((a, 42)));
+        while !rt.wait_idle(Duration::from_millis(20)) {}
+
+        // b keeps getting 1 and doubling it, so it is never idle
+        let b = rt.spawn(VM::init(DOUBLE.to_vec()), InputMode::Timeout {
+            timeout: Duration::from_millis(1),
+            value: 1,
+        });
+        assert_eq!(rt.recv_timeout(WAIT), Some((b, 2)));
+        assert!(!rt.wait_idle(Duration::from_millis(20)));
+        rt.send(b, &[0]);
+        rt.join();
+    }
+}
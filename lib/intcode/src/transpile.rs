@@ -0,0 +1,462 @@
+//! Ahead-of-time translation of intcode programs to Rust source.
+//!
+//! The generated module embeds the program, and runs it with [`crate::compiled::Machine`]
+//! which has the same I/O semantics as [`VM`](crate::VM). It is meant to be written from a
+//! build script:
+//!
+//! ```ignore
+//! // build.rs
+//! let program = intcode::parse_program(&std::fs::read_to_string("input.txt").unwrap());
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("program.rs");
+//! std::fs::write(out, intcode::transpile::transpile(&program)).unwrap();
+//!
+//! // main.rs
+//! mod program {
+//!     include!(concat!(env!("OUT_DIR"), "/program.rs"));
+//! }
+//! let mut vm = program::init();
+//! ```
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use crate::{Mode, disasm::Instruction};
+
+/// Instructions reachable from address 0, and the addresses execution may enter them at.
+struct Analysis<'m> {
+    code: BTreeMap<usize, Instruction<'m>>,
+    leaders: BTreeSet<usize>,
+    /// Parameter cells the program overwrites through position-mode writes. These are read
+    /// from memory at run time instead of being compiled in.
+    patched: BTreeSet<usize>,
+}
+
+fn compilable(inst: &Instruction) -> bool {
+    let write = match inst.opcode {
+        1 | 2 | 7 | 8 => Some(2),
+        3 => Some(0),
+        _ => None,
+    };
+    if let Some(i) = write
+        && let Mode::Immediate = inst.modes[i]
+    {
+        return false;
+    }
+    let jump_target = match inst.opcode {
+        5 | 6 => Some(1),
+        _ => None,
+    };
+    inst.modes
+        .iter()
+        .zip(inst.params)
+        .enumerate()
+        .all(|(i, (mode, &p))| match mode {
+            Mode::Position => p >= 0,
+            Mode::Immediate => Some(i) != jump_target || p >= 0,
+            Mode::Relative => true,
+        })
+}
+
+impl<'m> Analysis<'m> {
+    fn new(program: &'m [isize]) -> Self {
+        let mut patched = BTreeSet::new();
+        loop {
+            let analysis = Self::traverse(program, patched);
+            let found = analysis.patched_params();
+            if found.is_subset(&analysis.patched) {
+                return analysis;
+            }
+            patched = analysis.patched.union(&found).copied().collect();
+        }
+    }
+
+    fn traverse(program: &'m [isize], patched: BTreeSet<usize>) -> Self {
+        let mut analysis = Analysis {
+            code: BTreeMap::new(),
+            leaders: BTreeSet::from([0]),
+            patched,
+        };
+        let mut visited = BTreeSet::new();
+        let mut work = vec![0];
+
+        loop {
+            while let Some(addr) = work.pop() {
+                if !visited.insert(addr) {
+                    continue;
+                }
+                let Some(inst) = Instruction::decode(program, addr) else {
+                    continue;
+                };
+                if !compilable(&inst) {
+                    continue;
+                }
+                let next = addr + inst.size();
+                match inst.opcode {
+                    99 => (),
+                    5 | 6 => {
+                        if let Some(target) = analysis.immediate(&inst, 1) {
+                            analysis.leaders.insert(target);
+                            work.push(target);
+                        }
+                        if analysis.constant_jump(&inst) != Some(true) {
+                            analysis.leaders.insert(next);
+                            work.push(next);
+                        }
+                    }
+                    3 => {
+                        // Execution resumes here after waiting for input
+                        analysis.leaders.insert(addr);
+                        work.push(next);
+                    }
+                    _ => work.push(next),
+                }
+                analysis.code.insert(addr, inst);
+            }
+
+            // Calls push an immediate return address that points right after the jump.
+            // Follow those, since the fall-through of an unconditional jump isn't.
+            let after_jump = analysis
+                .code
+                .values()
+                .filter(|inst| matches!(inst.opcode, 5 | 6))
+                .map(|inst| inst.addr + inst.size())
+                .collect::<BTreeSet<_>>();
+            work = analysis
+                .immediates()
+                .filter(|addr| after_jump.contains(addr) && !visited.contains(addr))
+                .collect();
+            if work.is_empty() {
+                break;
+            }
+            analysis.leaders.extend(work.iter().copied());
+        }
+
+        // Any immediate pointing at an instruction may be an indirect jump target
+        let targets = analysis
+            .immediates()
+            .filter(|addr| analysis.code.contains_key(addr))
+            .collect::<Vec<_>>();
+        analysis.leaders.extend(targets);
+        let code = &analysis.code;
+        analysis.leaders.retain(|addr| code.contains_key(addr));
+
+        analysis
+    }
+
+    fn is_patched(&self, inst: &Instruction, i: usize) -> bool {
+        self.patched.contains(&(inst.addr + 1 + i))
+    }
+
+    fn immediate(&self, inst: &Instruction, i: usize) -> Option<usize> {
+        if let Mode::Immediate = inst.modes[i]
+            && !self.is_patched(inst, i)
+        {
+            inst.params[i].try_into().ok()
+        } else {
+            None
+        }
+    }
+
+    fn immediates(&self) -> impl Iterator<Item = usize> + '_ {
+        self.code
+            .values()
+            .flat_map(|inst| (0..inst.params.len()).filter_map(|i| self.immediate(inst, i)))
+    }
+
+    /// `Some(true)` if a jump is always taken, `Some(false)` if never, `None` if it depends
+    /// on memory.
+    fn constant_jump(&self, inst: &Instruction) -> Option<bool> {
+        if let Mode::Immediate = inst.modes[0]
+            && !self.is_patched(inst, 0)
+        {
+            match inst.opcode {
+                5 => Some(inst.params[0] != 0),
+                6 => Some(inst.params[0] == 0),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Parameter cells of compiled instructions which are targets of position-mode writes.
+    fn patched_params(&self) -> BTreeSet<usize> {
+        let params = self
+            .code
+            .values()
+            .flat_map(|inst| inst.addr + 1..inst.addr + inst.size())
+            .collect::<BTreeSet<_>>();
+        self.code
+            .values()
+            .filter_map(|inst| {
+                let i = match inst.opcode {
+                    1 | 2 | 7 | 8 => 2,
+                    3 => 0,
+                    _ => return None,
+                };
+                if let Mode::Position = inst.modes[i] {
+                    Some(inst.params[i] as usize)
+                } else {
+                    None
+                }
+            })
+            .filter(|addr| params.contains(addr))
+            .collect()
+    }
+
+    /// Address ranges covered by compiled instructions, merged. Patched parameters are
+    /// left out, since writing them doesn't invalidate the compiled code.
+    fn ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let cells = self
+            .code
+            .values()
+            .flat_map(|inst| inst.addr..inst.addr + inst.size())
+            .filter(|addr| !self.patched.contains(addr));
+        for addr in cells {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == addr => *end += 1,
+                _ => ranges.push((addr, addr + 1)),
+            }
+        }
+        ranges
+    }
+
+    fn read(&self, inst: &Instruction, i: usize) -> String {
+        let p = inst.params[i];
+        let cell = inst.addr + 1 + i;
+        match (&inst.modes[i], self.is_patched(inst, i)) {
+            (Mode::Position, false) => format!("m.load({})", p),
+            (Mode::Position, true) => format!("m.load(m.ptr({}))", cell),
+            (Mode::Immediate, false) => format!("{}_isize", p),
+            (Mode::Immediate, true) => format!("m.load({})", cell),
+            (Mode::Relative, false) => format!("m.load(m.rel({}))", p),
+            (Mode::Relative, true) => format!("m.load(m.rel(m.load({})))", cell),
+        }
+    }
+
+    fn ptr(&self, inst: &Instruction, i: usize) -> String {
+        let p = inst.params[i];
+        let cell = inst.addr + 1 + i;
+        match (&inst.modes[i], self.is_patched(inst, i)) {
+            (Mode::Position, false) => p.to_string(),
+            (Mode::Position, true) => format!("m.ptr({})", cell),
+            (Mode::Immediate, _) => unreachable!(),
+            (Mode::Relative, false) => format!("m.rel({})", p),
+            (Mode::Relative, true) => format!("m.rel(m.load({}))", cell),
+        }
+    }
+}
+
+fn store(out: &mut String, analysis: &Analysis, inst: &Instruction, i: usize, next: usize) {
+    writeln!(
+        out,
+        "                if m.store({}, v) {{ m.set_pc({}); return Exit::Modified; }}",
+        analysis.ptr(inst, i),
+        next
+    )
+    .unwrap();
+}
+
+/// Emit the straight-line code starting at `leader`, up to the next jump or leader.
+fn block(out: &mut String, analysis: &Analysis, leader: usize) {
+    writeln!(out, "            {} => {{", leader).unwrap();
+    let mut addr = leader;
+    loop {
+        let inst = &analysis.code[&addr];
+        let next = addr + inst.size();
+        writeln!(out, "                // {}: {}", addr, inst).unwrap();
+        match inst.opcode {
+            1 | 2 | 7 | 8 => {
+                let (x, y) = (analysis.read(inst, 0), analysis.read(inst, 1));
+                let v = match inst.opcode {
+                    1 => format!("{} + {}", x, y),
+                    2 => format!("{} * {}", x, y),
+                    7 => format!("({} < {}) as isize", x, y),
+                    8 => format!("({} == {}) as isize", x, y),
+                    _ => unreachable!(),
+                };
+                writeln!(out, "                let v: isize = {};", v).unwrap();
+                store(out, analysis, inst, 2, next);
+            }
+            3 => {
+                writeln!(
+                    out,
+                    "                let Some(v) = m.input() else {{ m.set_pc({}); return Exit::Pending; }};",
+                    addr
+                )
+                .unwrap();
+                store(out, analysis, inst, 0, next);
+            }
+            4 => {
+                writeln!(out, "                m.output({});", analysis.read(inst, 0)).unwrap();
+            }
+            5 | 6 => {
+                let jump = match analysis.immediate(inst, 1) {
+                    Some(target) => format!("m.set_pc({}); continue;", target),
+                    None => format!("m.jump({}); continue;", analysis.read(inst, 1)),
+                };
+                match analysis.constant_jump(inst) {
+                    Some(true) => {
+                        writeln!(out, "                {}", jump).unwrap();
+                        break;
+                    }
+                    Some(false) => (),
+                    None => {
+                        let op = if inst.opcode == 5 { "!=" } else { "==" };
+                        writeln!(
+                            out,
+                            "                if {} {} 0 {{ {} }}",
+                            analysis.read(inst, 0),
+                            op,
+                            jump
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+            9 => {
+                writeln!(
+                    out,
+                    "                m.adjust_base({});",
+                    analysis.read(inst, 0)
+                )
+                .unwrap();
+            }
+            99 => {
+                writeln!(
+                    out,
+                    "                m.set_pc({}); return Exit::Halt;",
+                    addr
+                )
+                .unwrap();
+                break;
+            }
+            _ => unreachable!(),
+        }
+        if matches!(inst.opcode, 5 | 6)
+            || analysis.leaders.contains(&next)
+            || !analysis.code.contains_key(&next)
+        {
+            writeln!(out, "                m.set_pc({}); continue;", next).unwrap();
+            break;
+        }
+        addr = next;
+    }
+    writeln!(out, "            }}").unwrap();
+}
+
+/// Translate `program` to the source of a Rust module with `PROGRAM`, `CODE`, `run` and `init`.
+pub fn transpile(program: &[isize]) -> String {
+    let analysis = Analysis::new(program);
+    let mut out = String::new();
+    writeln!(out, "// Generated by intcode::transpile. Do not edit.").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "use intcode::compiled::{{Exit, Machine}};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "pub static PROGRAM: &[isize] = &{:?};", program).unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "pub static CODE: &[(usize, usize)] = &{:?};",
+        analysis.ranges()
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "pub fn init() -> Machine {{").unwrap();
+    writeln!(out, "    Machine::new(PROGRAM.to_vec(), CODE, run)").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "#[allow(clippy::all)]").unwrap();
+    writeln!(out, "pub fn run(m: &mut Machine) -> Exit {{").unwrap();
+    writeln!(out, "    loop {{").unwrap();
+    writeln!(out, "        match m.pc() {{").unwrap();
+    for &leader in &analysis.leaders {
+        block(&mut out, &analysis, leader);
+    }
+    writeln!(out, "            _ => return Exit::Unknown,").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Status,
+        compiled::{Exit, Machine},
+    };
+    use alloc::vec;
+
+    #[test]
+    fn test_analysis() {
+        // Output 999/1000/1001 if the input is below/equal/above 8
+        let program = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.leaders.iter().copied().collect::<Vec<_>>(), vec![
+            0, 9, 16, 22, 31, 36, 46
+        ]);
+        // 19..22 and 45 are data
+        assert_eq!(analysis.ranges(), vec![(0, 19), (22, 45), (46, 47)]);
+
+        let source = transpile(&program);
+        assert!(source.contains("            46 => {\n"));
+        assert!(
+            source.contains("let Some(v) = m.input() else { m.set_pc(0); return Exit::Pending; };")
+        );
+        assert!(source.contains("if m.load(20) != 0 { m.set_pc(22); continue; }"));
+    }
+
+    #[test]
+    fn test_return_site() {
+        // Call a subroutine at 10 that outputs 7, then output 8 at the return site
+        let program = [
+            1101, 7, 0, 100, 1105, 1, 10, 104, 8, 99, 104, 7, 106, 0, 100,
+        ];
+        let analysis = Analysis::new(&program);
+        assert!(analysis.leaders.contains(&7));
+        assert_eq!(analysis.ranges(), vec![(0, 15)]);
+    }
+
+    #[test]
+    fn test_patched() {
+        // Overwrite the target of the jump at 4 with 9, to skip `out #0`
+        let program = [1101, 9, 0, 6, 1105, 1, 7, 104, 0, 104, 1, 99];
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.patched, BTreeSet::from([6]));
+        assert_eq!(analysis.ranges(), vec![(0, 6)]);
+        assert!(transpile(&program).contains("                m.jump(m.load(6)); continue;\n"));
+    }
+
+    // Compiled code that never matches, so every step goes through the interpreter
+    fn interpret(_m: &mut Machine) -> Exit {
+        Exit::Unknown
+    }
+
+    #[test]
+    fn test_fallback() {
+        // Overwrite the output instruction at 4 with `out #42`
+        let program = vec![1101, 0, 104, 4, 4, 42, 99];
+        let mut machine = Machine::new(program.clone(), &[(0, 4)], interpret);
+        assert_eq!(machine.run(), Status::Halted);
+        assert!(!machine.is_fallback());
+        assert_eq!(machine.read_all(), vec![42]);
+
+        let mut machine = Machine::new(program, &[(0, 7)], interpret);
+        assert_eq!(machine.run(), Status::Halted);
+        assert!(machine.is_fallback());
+        assert_eq!(machine.read_all(), vec![42]);
+    }
+}